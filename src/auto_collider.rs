@@ -0,0 +1,468 @@
+use bevy::{
+    asset::HandleId,
+    prelude::*,
+    render::render_resource::TextureFormat,
+    utils::HashMap,
+};
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+
+use crate::game::GameState;
+
+fn half() -> f32 {
+    0.5
+}
+
+fn tolerance() -> f32 {
+    1.5
+}
+
+/// Loops with a smaller area, in pixels, are specks and left out.
+const MIN_LOOP_AREA: f32 = 4.;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+pub enum TraceMode {
+    /// Solid convex pieces, nothing can end up inside.
+    #[default]
+    ConvexDecomposition,
+    /// Only the outline, cheaper but hollow.
+    Polyline,
+}
+
+/// How to turn a sprite's alpha into a collider.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct TraceSettings {
+    /// Pixels with at least this alpha, from 0 to 1, are solid.
+    #[serde(default = "half")]
+    pub threshold: f32,
+    /// Furthest the simplified outline may stray from the traced one, in
+    /// pixels.
+    #[serde(default = "tolerance")]
+    pub tolerance: f32,
+    #[serde(default)]
+    pub mode: TraceMode,
+}
+
+impl Default for TraceSettings {
+    fn default() -> Self {
+        Self {
+            threshold: half(),
+            tolerance: tolerance(),
+            mode: TraceMode::default(),
+        }
+    }
+}
+
+/// Gets a collider traced from `image` once it has loaded, and again
+/// whenever the image is reloaded.
+#[derive(Component, Clone, Debug)]
+pub struct AutoCollider {
+    pub image: Handle<Image>,
+    /// Size the image is drawn at, its own size when `None`.
+    pub size: Option<Vec2>,
+    pub settings: TraceSettings,
+}
+
+type OutlineKey = (HandleId, u32, u32);
+type ColliderKey = (HandleId, u32, u32, TraceMode, [u32; 2]);
+
+/// Outlines and colliders built so far, so each image is traced once per
+/// settings and decomposed once per size however many props use it.
+#[derive(Resource, Default)]
+pub struct TraceCache {
+    /// In pixels.
+    outlines: HashMap<OutlineKey, Vec<Vec<Vec2>>>,
+    /// `None` when the image has nothing solid.
+    colliders: HashMap<ColliderKey, Option<Collider>>,
+}
+
+impl TraceCache {
+    pub fn outlines(&mut self, handle: &Handle<Image>, image: &Image, settings: &TraceSettings) -> &Vec<Vec<Vec2>> {
+        let key = (handle.id(), settings.threshold.to_bits(), settings.tolerance.to_bits());
+        self.outlines.entry(key).or_insert_with(|| {
+            trace_outlines(image, settings.threshold)
+                .into_iter()
+                .map(|outline| simplify_loop(&outline, settings.tolerance))
+                .filter(|outline| outline.len() >= 3 && area(outline).abs() >= MIN_LOOP_AREA)
+                .collect()
+        })
+    }
+
+    /// The collider for `image` drawn at `size`.
+    pub fn collider(&mut self, handle: &Handle<Image>, image: &Image, settings: &TraceSettings, size: Vec2) -> Option<Collider> {
+        let key = (
+            handle.id(),
+            settings.threshold.to_bits(),
+            settings.tolerance.to_bits(),
+            settings.mode,
+            [size.x.to_bits(), size.y.to_bits()],
+        );
+        if let Some(collider) = self.colliders.get(&key) {
+            return collider.clone();
+        }
+
+        let outlines = self.outlines(handle, image, settings);
+        let collider = match outlines.is_empty() {
+            true => {
+                warn!("nothing solid to trace in {:?}", handle);
+                None
+            }
+            false => Some(outline_collider(outlines, image.size(), size, settings.mode)),
+        };
+        self.colliders.insert(key, collider.clone());
+        collider
+    }
+
+    /// Forgets everything built from `image`.
+    pub fn clear(&mut self, image: HandleId) {
+        self.outlines.retain(|key, _| key.0 != image);
+        self.colliders.retain(|key, _| key.0 != image);
+    }
+}
+
+/// Which pixels of `image` are at least `threshold` opaque, row by row from
+/// the top.
+fn alpha_mask(image: &Image, threshold: f32) -> Option<(Vec<bool>, usize, usize)> {
+    let converted;
+    let image = match image.texture_descriptor.format {
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => image,
+        _ => {
+            converted = image.convert(TextureFormat::Rgba8UnormSrgb)?;
+            &converted
+        }
+    };
+
+    let size = image.texture_descriptor.size;
+    let (width, height) = (size.width as usize, size.height as usize);
+    let threshold = (threshold.clamp(0., 1.) * 255.).round() as u8;
+    let mask = image.data
+        .chunks_exact(4)
+        .take(width * height)
+        .map(|pixel| pixel[3] >= threshold)
+        .collect();
+    Some((mask, width, height))
+}
+
+/// Outlines of the solid regions of `image` found with marching squares, in
+/// pixels from the top left corner. Holes are left out, so they end up
+/// filled.
+pub fn trace_outlines(image: &Image, threshold: f32) -> Vec<Vec<Vec2>> {
+    let Some((mask, width, height)) = alpha_mask(image, threshold) else {
+        warn!("can't trace an image in {:?}", image.texture_descriptor.format);
+        return vec![];
+    };
+    let solid = |x: i32, y: i32| {
+        x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height
+            && mask[y as usize * width + x as usize]
+    };
+
+    // cells sit between pixel centers, one more than the pixels each way so
+    // outlines close around the border. Points are in half pixels to stay
+    // exact, and every point is shared by exactly two segments.
+    let mut neighbours: HashMap<IVec2, Vec<IVec2>> = HashMap::default();
+    let mut connect = |a: IVec2, b: IVec2| {
+        neighbours.entry(a).or_default().push(b);
+        neighbours.entry(b).or_default().push(a);
+    };
+    for y in -1..height as i32 {
+        for x in -1..width as i32 {
+            let (top_left, top_right) = (solid(x, y), solid(x + 1, y));
+            let (bottom_right, bottom_left) = (solid(x + 1, y + 1), solid(x, y + 1));
+
+            let top = IVec2::new(2 * x + 1, 2 * y);
+            let right = IVec2::new(2 * x + 2, 2 * y + 1);
+            let bottom = IVec2::new(2 * x + 1, 2 * y + 2);
+            let left = IVec2::new(2 * x, 2 * y + 1);
+
+            let crossed: Vec<IVec2> = [
+                (top, top_left != top_right),
+                (right, top_right != bottom_right),
+                (bottom, bottom_right != bottom_left),
+                (left, bottom_left != top_left),
+            ]
+                .into_iter()
+                .filter_map(|(point, crossed)| crossed.then_some(point))
+                .collect();
+
+            match crossed.len() {
+                2 => connect(crossed[0], crossed[1]),
+                // saddle, cut off the two empty corners
+                4 if top_left => {
+                    connect(top, right);
+                    connect(bottom, left);
+                }
+                4 => {
+                    connect(top, left);
+                    connect(right, bottom);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut loops = vec![];
+    let mut starts: Vec<IVec2> = neighbours.keys().copied().collect();
+    starts.sort_by_key(|point| (point.y, point.x));
+    let mut visited = bevy::utils::HashSet::default();
+    for start in starts {
+        if !visited.insert(start) {
+            continue;
+        }
+        let mut points = vec![start];
+        let (mut previous, mut current) = (start, neighbours[&start][0]);
+        while current != start && visited.insert(current) {
+            points.push(current);
+            let next = neighbours[&current].iter().copied().find(|&point| point != previous).unwrap_or(start);
+            (previous, current) = (current, next);
+        }
+        loops.push(points.into_iter().map(|point| (point.as_vec2() + 1.) / 2.).collect::<Vec<Vec2>>());
+    }
+
+    // a loop inside an odd number of others is a hole
+    let outer = |index: usize| {
+        let point = loops[index][0];
+        loops.iter().enumerate()
+            .filter(|&(other, outline)| other != index && contains(outline, point))
+            .count() % 2 == 0
+    };
+    (0..loops.len()).filter(|&index| outer(index)).map(|index| loops[index].clone()).collect()
+}
+
+fn area(outline: &[Vec2]) -> f32 {
+    let mut area = 0.;
+    for (i, a) in outline.iter().enumerate() {
+        let b = outline[(i + 1) % outline.len()];
+        area += a.perp_dot(b);
+    }
+    area / 2.
+}
+
+fn contains(outline: &[Vec2], point: Vec2) -> bool {
+    let mut inside = false;
+    for (i, a) in outline.iter().enumerate() {
+        let b = outline[(i + 1) % outline.len()];
+        if (a.y > point.y) != (b.y > point.y) && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+fn distance_to_segment(point: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let segment = b - a;
+    let t = match segment.length_squared() > 0. {
+        true => ((point - a).dot(segment) / segment.length_squared()).clamp(0., 1.),
+        false => 0.,
+    };
+    point.distance(a + segment * t)
+}
+
+/// Douglas-Peucker, keeping both ends.
+fn simplify_line(points: &[Vec2], tolerance: f32) -> Vec<Vec2> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let (first, last) = (points[0], points[points.len() - 1]);
+    let (index, distance) = points[1..points.len() - 1].iter()
+        .enumerate()
+        .map(|(i, &point)| (i + 1, distance_to_segment(point, first, last)))
+        .fold((0, 0.), |furthest, candidate| if candidate.1 > furthest.1 { candidate } else { furthest });
+
+    if distance <= tolerance {
+        return vec![first, last];
+    }
+    let mut simplified = simplify_line(&points[..=index], tolerance);
+    simplified.pop();
+    simplified.extend(simplify_line(&points[index..], tolerance));
+    simplified
+}
+
+/// Douglas-Peucker for a closed loop, split at the point furthest from the
+/// first one so both halves have fixed ends.
+pub fn simplify_loop(outline: &[Vec2], tolerance: f32) -> Vec<Vec2> {
+    let Some(first) = outline.first().copied() else {
+        return vec![];
+    };
+    let split = (0..outline.len())
+        .max_by(|&a, &b| outline[a].distance_squared(first).total_cmp(&outline[b].distance_squared(first)))
+        .unwrap_or(0);
+    if split == 0 {
+        return outline.to_vec();
+    }
+
+    let mut closed = outline.to_vec();
+    closed.push(first);
+    let mut simplified = simplify_line(&closed[..=split], tolerance);
+    simplified.pop();
+    simplified.extend(simplify_line(&closed[split..], tolerance));
+    simplified.pop();
+    simplified
+}
+
+/// Collider for `outlines` traced from an image of `image_size` pixels,
+/// drawn at `size` and centered on the entity.
+pub fn outline_collider(outlines: &[Vec<Vec2>], image_size: Vec2, size: Vec2, mode: TraceMode) -> Collider {
+    let scale = size / image_size;
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    for outline in outlines {
+        let start = vertices.len() as u32;
+        let count = outline.len() as u32;
+        vertices.extend(outline.iter().map(|point| {
+            Vec2::new(point.x - image_size.x / 2., image_size.y / 2. - point.y) * scale
+        }));
+        indices.extend((0..count).map(|i| [start + i, start + (i + 1) % count]));
+    }
+
+    match mode {
+        TraceMode::ConvexDecomposition => Collider::convex_decomposition(&vertices, &indices),
+        TraceMode::Polyline => Collider::polyline(vertices, Some(indices)),
+    }
+}
+
+fn build_auto_colliders(
+    mut commands: Commands,
+    images: Res<Assets<Image>>,
+    mut cache: ResMut<TraceCache>,
+    query: Query<(Entity, &AutoCollider), Without<Traced>>,
+) {
+    for (entity, auto_collider) in query.iter() {
+        let Some(image) = images.get(&auto_collider.image) else {
+            continue;
+        };
+
+        let size = auto_collider.size.unwrap_or(image.size());
+        let mut entity = commands.entity(entity);
+        entity.insert(Traced);
+        if let Some(collider) = cache.collider(&auto_collider.image, image, &auto_collider.settings, size) {
+            entity.insert(collider);
+        }
+    }
+}
+
+/// Marks auto colliders that are up to date with their image.
+#[derive(Component)]
+struct Traced;
+
+/// Traces reloaded images again, and rebuilds the colliders made from them.
+fn retrace_modified_images(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<Image>>,
+    mut cache: ResMut<TraceCache>,
+    query: Query<(Entity, &AutoCollider), With<Traced>>,
+) {
+    for event in events.iter() {
+        let AssetEvent::Modified { handle } = event else {
+            continue;
+        };
+
+        cache.clear(handle.id());
+        for (entity, auto_collider) in query.iter() {
+            if auto_collider.image == *handle {
+                commands.entity(entity).remove::<(Collider, Traced)>();
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct AutoColliderPlugin;
+
+impl Plugin for AutoColliderPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<TraceCache>()
+            .add_systems(Update, (
+                retrace_modified_images,
+                apply_deferred,
+                build_auto_colliders.run_if(in_state(GameState::InGame)),
+            ).chain());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::render_resource::{Extent3d, TextureDimension};
+
+    use super::*;
+
+    /// `#` is opaque, anything else transparent.
+    fn image(rows: &[&str]) -> Image {
+        let data = rows.iter()
+            .flat_map(|row| row.chars())
+            .flat_map(|pixel| [255, 255, 255, if pixel == '#' { 255 } else { 0 }])
+            .collect();
+        let size = Extent3d {
+            width: rows[0].len() as u32,
+            height: rows.len() as u32,
+            depth_or_array_layers: 1,
+        };
+        Image::new(size, TextureDimension::D2, data, TextureFormat::Rgba8UnormSrgb)
+    }
+
+    #[test]
+    fn solid_square() {
+        let loops = trace_outlines(&image(&[
+            "....",
+            ".##.",
+            ".##.",
+            "....",
+        ]), 0.5);
+
+        assert_eq!(loops.len(), 1);
+        // marching squares cuts the corners off
+        assert_eq!(loops[0].len(), 8);
+        assert_eq!(area(&loops[0]).abs(), 3.5);
+        assert_eq!(simplify_loop(&loops[0], 0.5).len(), 4);
+    }
+
+    #[test]
+    fn ring_hole_is_filled() {
+        let loops = trace_outlines(&image(&[
+            "#####",
+            "#...#",
+            "#...#",
+            "#...#",
+            "#####",
+        ]), 0.5);
+
+        // only the outer loop is left
+        assert_eq!(loops.len(), 1);
+        assert_eq!(area(&loops[0]).abs(), 24.5);
+        let simplified = simplify_loop(&loops[0], 0.5);
+        assert_eq!(simplified.len(), 4);
+        assert!(simplified.iter().all(|point| point.x <= 0.5 || point.x >= 4.5 || point.y <= 0.5 || point.y >= 4.5));
+    }
+
+    #[test]
+    fn diagonal_pixels_join() {
+        for rows in [["#.", ".#"], [".#", "#."]] {
+            let loops = trace_outlines(&image(&rows), 0.5);
+
+            assert_eq!(loops.len(), 1, "{rows:?}");
+            assert_eq!(loops[0].len(), 8, "{rows:?}");
+            assert_eq!(simplify_loop(&loops[0], 0.5).len(), 4, "{rows:?}");
+        }
+    }
+
+    #[test]
+    fn threshold() {
+        let mut image = image(&["##"]);
+        image.data[3] = 100;
+
+        assert_eq!(trace_outlines(&image, 0.5)[0].len(), 4);
+        assert_eq!(trace_outlines(&image, 0.3)[0].len(), 6);
+    }
+
+    #[test]
+    fn simplify_drops_collinear_points() {
+        let outline: Vec<Vec2> = [(0., 0.), (1., 0.), (2., 0.), (2., 1.), (2., 2.), (1., 2.), (0., 2.), (0., 1.)]
+            .into_iter()
+            .map(|(x, y)| Vec2::new(x, y))
+            .collect();
+
+        let simplified = simplify_loop(&outline, 0.1);
+        assert_eq!(simplified.len(), 4);
+        assert!(simplified.contains(&Vec2::new(0., 0.)) && simplified.contains(&Vec2::new(2., 2.)));
+    }
+}
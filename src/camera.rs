@@ -1,38 +1,446 @@
-use std::{fmt::Debug, hash::Hash};
-
 use bevy::{
+    input::mouse::MouseWheel,
     math::Vec2,
     prelude::*,
+    render::camera::CameraUpdateSystem,
+    transform::TransformSystem,
 };
 
-use crate::game::{Player, GameState};
+use crate::{game::Player, input::Velocity, camera_shake::{CameraShake, advance_camera_shake}};
 
-fn camera_movement(
-    mut transforms: ParamSet<(
-        Query<&mut Transform, With<Camera>>,
-        Query<&Transform, With<Player>>,
-    )>,
-    mut player_position: Local<Vec2>,
+/// World units per second at scale 1.
+const FREE_PAN_SPEED: f32 = 600.;
+
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub enum CameraSet {
+    /// Changes projection scales, before bevy recomputes the view areas.
+    Zoom,
+    /// Moves the cameras, after physics has moved what they follow.
+    Follow,
+}
+
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct CameraBindings {
+    pub zoom_in: KeyCode,
+    pub zoom_out: KeyCode,
+    /// Held to make the mouse wheel zoom instead of scrolling the hotbar.
+    pub zoom_modifier: KeyCode,
+    /// Switches between following the player and panning freely.
+    pub toggle_free_pan: KeyCode,
+    pub pan_up: KeyCode,
+    pub pan_down: KeyCode,
+    pub pan_left: KeyCode,
+    pub pan_right: KeyCode,
+}
+
+impl Default for CameraBindings {
+    fn default() -> Self {
+        Self {
+            zoom_in: KeyCode::Equals,
+            zoom_out: KeyCode::Minus,
+            zoom_modifier: KeyCode::ControlLeft,
+            toggle_free_pan: KeyCode::F2,
+            pan_up: KeyCode::Up,
+            pan_down: KeyCode::Down,
+            pan_left: KeyCode::Left,
+            pan_right: KeyCode::Right,
+        }
+    }
+}
+
+/// Lets the player zoom a camera, eased towards the requested scale.
+#[derive(Component, Clone, Debug)]
+pub struct CameraZoom {
+    /// Smallest projection scale, the closest the camera gets.
+    pub min: f32,
+    pub max: f32,
+    /// Factor the scale changes by per wheel notch or key press.
+    pub step: f32,
+    /// Seconds to close half the gap to the requested scale, 0 snaps.
+    pub half_life: f32,
+    target: f32,
+    /// Eased scale, before zoom pulses are applied.
+    current: Option<f32>,
+}
+
+impl Default for CameraZoom {
+    fn default() -> Self {
+        Self {
+            min: 0.5,
+            max: 2.,
+            step: 1.15,
+            half_life: 0.08,
+            target: 1.,
+            current: None,
+        }
+    }
+}
+
+impl CameraZoom {
+    /// Projection scale the camera is easing towards.
+    pub fn target(&self) -> f32 {
+        self.target
+    }
+
+    pub fn set_target(&mut self, scale: f32) {
+        self.target = scale.clamp(self.min, self.max);
+    }
+
+    pub fn zoom_by(&mut self, factor: f32) {
+        self.set_target(self.target * factor);
+    }
+}
+
+/// What a camera with [`CameraFollow`] looks at. Cameras without a mode
+/// follow.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq)]
+pub enum CameraMode {
+    /// Chases the follow target with its deadzone and look-ahead.
+    #[default]
+    Follow,
+    /// Moved with the pan keys, ignoring bounds. For debugging.
+    FreePan { speed: f32 },
+    /// Eases to a point and stays there, e.g. for cutscenes.
+    Fixed { position: Vec2 },
+    /// Keeps an entity centered, without deadzone or look-ahead.
+    Focus { target: Entity },
+}
+
+/// Makes a camera chase an entity. Cameras without it stay where they are.
+#[derive(Component, Clone, Debug)]
+pub struct CameraFollow {
+    /// Entity to follow, the player when `None`.
+    pub target: Option<Entity>,
+    /// Seconds to close half the distance to the goal, 0 snaps.
+    pub half_life: f32,
+    /// Half size of the rect around the view center the target can move in
+    /// without the camera moving.
+    pub deadzone: Vec2,
+    /// Fraction of the cursor's distance from the screen center to lead by.
+    pub cursor_look_ahead: f32,
+    /// Distance to lead by in the direction the target moves.
+    pub velocity_look_ahead: f32,
+    /// Longest total lead, in world units.
+    pub max_look_ahead: f32,
+    /// Smoothed view center, before any effects are applied.
+    position: Option<Vec2>,
+}
+
+impl Default for CameraFollow {
+    fn default() -> Self {
+        Self {
+            target: None,
+            half_life: 0.12,
+            deadzone: Vec2::new(24., 16.),
+            cursor_look_ahead: 0.2,
+            velocity_look_ahead: 40.,
+            max_look_ahead: 120.,
+            position: None,
+        }
+    }
+}
+
+impl CameraFollow {
+    pub fn with_target(mut self, target: Entity) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    /// Where the camera looks, without effects. `None` until it has found
+    /// its target.
+    pub fn position(&self) -> Option<Vec2> {
+        self.position
+    }
+
+    /// Jumps straight to the target on the next update, e.g. after a teleport.
+    pub fn snap(&mut self) {
+        self.position = None;
+    }
+
+    fn look_ahead(&self, cursor_offset: Option<Vec2>, velocity: Vec2) -> Vec2 {
+        let cursor = cursor_offset.unwrap_or_default() * self.cursor_look_ahead;
+        let movement = velocity.normalize_or_zero() * self.velocity_look_ahead;
+        (cursor + movement).clamp_length_max(self.max_look_ahead)
+    }
+
+    /// Moves towards `goal` for `delta` seconds, only once it leaves
+    /// `deadzone`.
+    fn update(&mut self, goal: Vec2, deadzone: Vec2, delta: f32) -> Vec2 {
+        let Some(position) = self.position else {
+            self.position = Some(goal);
+            return goal;
+        };
+
+        let offset = goal - position;
+        let outside = offset - offset.clamp(-deadzone, deadzone);
+        let step = match self.half_life > 0. {
+            true => 1. - 0.5_f32.powf(delta / self.half_life),
+            false => 1.,
+        };
+
+        let position = position + outside * step;
+        self.position = Some(position);
+        position
+    }
+}
+
+/// World rect a camera's view has to stay inside. Overrides the
+/// [`LevelBounds`] for that camera.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct CameraBounds {
+    pub rect: Rect,
+}
+
+/// Marks a sprite whose extent the level is made of, usually the background.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct BoundsSource;
+
+/// Bounds for cameras without their own [`CameraBounds`]. Derived from the
+/// [`BoundsSource`] sprites, or set by hand when there are none.
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq)]
+pub struct LevelBounds(pub Option<Rect>);
+
+/// Moves `center` so a view covering `area` around it stays inside
+/// `bounds`. Centers on any axis the view is larger than the bounds.
+fn clamp_view(center: Vec2, area: Rect, bounds: Rect) -> Vec2 {
+    let min = bounds.min - area.min;
+    let max = bounds.max - area.max;
+    let clamp_axis = |value: f32, min: f32, max: f32, middle: f32| match min <= max {
+        true => value.clamp(min, max),
+        false => middle,
+    };
+
+    Vec2::new(
+        clamp_axis(center.x, min.x, max.x, bounds.center().x),
+        clamp_axis(center.y, min.y, max.y, bounds.center().y),
+    )
+}
+
+fn update_level_bounds(
+    images: Res<Assets<Image>>,
+    sources: Query<(&GlobalTransform, &Sprite, &Handle<Image>), With<BoundsSource>>,
+    mut level_bounds: ResMut<LevelBounds>,
 ) {
-    for player_transform in transforms.p1().iter() {
-        *player_position = Vec2::new(
-            player_transform.translation.x,
-            player_transform.translation.y,
-        );
+    let mut bounds: Option<Rect> = None;
+    for (transform, sprite, image) in sources.iter() {
+        let Some(size) = sprite.custom_size.or_else(|| images.get(image).map(|image| image.size())) else {
+            continue;
+        };
+        let (scale, _, translation) = transform.to_scale_rotation_translation();
+        let size = size * scale.truncate();
+        let center = translation.truncate() - sprite.anchor.as_vec() * size;
+        let rect = Rect::from_center_size(center, size);
+        bounds = Some(bounds.map_or(rect, |bounds| bounds.union(rect)));
     }
 
-    for mut camera_transform in transforms.p0().iter_mut() {
-        *camera_transform = Transform::from_xyz(player_position.x, player_position.y, camera_transform.translation.z);
+    if bounds.is_some() {
+        level_bounds.set_if_neq(LevelBounds(bounds));
     }
 }
 
-pub trait CameraState: Debug + Clone + Copy + PartialEq + Eq + Hash + Sync + Send {}
+fn zoom_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    bindings: Res<CameraBindings>,
+    mut wheel: EventReader<MouseWheel>,
+    mut zooms: Query<&mut CameraZoom>,
+) {
+    let mut notches = 0;
+    if keyboard_input.just_pressed(bindings.zoom_in) {
+        notches += 1;
+    }
+    if keyboard_input.just_pressed(bindings.zoom_out) {
+        notches -= 1;
+    }
+
+    let wheel_zooms = keyboard_input.pressed(bindings.zoom_modifier);
+    for event in wheel.iter().filter(|_| wheel_zooms) {
+        if event.y > 0. {
+            notches += 1;
+        } else if event.y < 0. {
+            notches -= 1;
+        }
+    }
+
+    if notches == 0 {
+        return;
+    }
+    for mut zoom in zooms.iter_mut() {
+        let factor = zoom.step.powi(-notches);
+        zoom.zoom_by(factor);
+    }
+}
+
+fn apply_zoom(
+    time: Res<Time>,
+    mut cameras: Query<(&mut CameraZoom, &mut OrthographicProjection, Option<&CameraShake>)>,
+) {
+    for (mut zoom, mut projection, shake) in cameras.iter_mut() {
+        let step = match zoom.half_life > 0. {
+            true => 1. - 0.5_f32.powf(time.delta_seconds() / zoom.half_life),
+            false => 1.,
+        };
+        let current = zoom.current.unwrap_or(projection.scale);
+        let mut scale = current + (zoom.target - current) * step;
+        // settle instead of creeping towards the target forever
+        if (zoom.target - scale).abs() < 1e-3 {
+            scale = zoom.target;
+        }
+        zoom.current = Some(scale);
+
+        let scale = scale * shake.map_or(1., |shake| shake.zoom_multiplier());
+
+        // only touch the projection when it changes, bevy recomputes the
+        // view area whenever it does
+        if projection.scale != scale {
+            projection.scale = scale;
+        }
+    }
+}
+
+fn toggle_free_pan(
+    keyboard_input: Res<Input<KeyCode>>,
+    bindings: Res<CameraBindings>,
+    mut cameras: Query<&mut CameraMode>,
+) {
+    if !keyboard_input.just_pressed(bindings.toggle_free_pan) {
+        return;
+    }
+    for mut mode in cameras.iter_mut() {
+        *mode = match *mode {
+            CameraMode::FreePan { .. } => CameraMode::Follow,
+            _ => CameraMode::FreePan { speed: FREE_PAN_SPEED },
+        };
+    }
+}
 
-#[derive(Default)]
-pub struct CameraPlugin;
+fn pan_direction(keyboard_input: &Input<KeyCode>, bindings: &CameraBindings) -> Vec2 {
+    let mut direction = Vec2::ZERO;
+    if keyboard_input.pressed(bindings.pan_up) {
+        direction.y += 1.;
+    }
+    if keyboard_input.pressed(bindings.pan_down) {
+        direction.y -= 1.;
+    }
+    if keyboard_input.pressed(bindings.pan_left) {
+        direction.x -= 1.;
+    }
+    if keyboard_input.pressed(bindings.pan_right) {
+        direction.x += 1.;
+    }
+    direction.normalize_or_zero()
+}
+
+fn camera_movement(
+    time: Res<Time>,
+    windows: Query<&Window>,
+    keyboard_input: Res<Input<KeyCode>>,
+    bindings: Res<CameraBindings>,
+    level_bounds: Res<LevelBounds>,
+    mut cameras: Query<(&mut CameraFollow, &mut Transform, Option<&CameraMode>, Option<&OrthographicProjection>, Option<&CameraBounds>, Option<&CameraShake>)>,
+    targets: Query<(&Transform, Option<&Velocity>), Without<CameraFollow>>,
+    player_q: Query<Entity, With<Player>>,
+) {
+    // the cursor relative to the screen center, so the lead doesn't chase
+    // its own effect on the cursor's world position
+    let cursor_offset = windows.get_single().ok().and_then(|window| {
+        let cursor = window.cursor_position()?;
+        let offset = cursor - Vec2::new(window.width(), window.height()) / 2.;
+        Some(Vec2::new(offset.x, -offset.y))
+    });
+    let delta = time.delta_seconds();
+
+    for (mut follow, mut transform, mode, projection, bounds, shake) in cameras.iter_mut() {
+        let scale = projection.map_or(1., |projection| projection.scale);
+        let mode = mode.copied().unwrap_or_default();
+
+        let mut position = match mode {
+            CameraMode::Follow => {
+                let Some(target) = follow.target.or_else(|| player_q.get_single().ok()) else {
+                    continue;
+                };
+                let Ok((target_transform, velocity)) = targets.get(target) else {
+                    continue;
+                };
+
+                let look_ahead = follow.look_ahead(
+                    cursor_offset.map(|offset| offset * scale),
+                    velocity.map_or(Vec2::ZERO, |velocity| velocity.0),
+                );
+                let goal = target_transform.translation.truncate() + look_ahead;
+                let deadzone = follow.deadzone;
+                follow.update(goal, deadzone, delta)
+            }
+            CameraMode::FreePan { speed } => {
+                let current = follow.position.unwrap_or(transform.translation.truncate());
+                let position = current + pan_direction(&keyboard_input, &bindings) * speed * scale * delta;
+                follow.position = Some(position);
+                position
+            }
+            CameraMode::Fixed { position } => follow.update(position, Vec2::ZERO, delta),
+            CameraMode::Focus { target } => {
+                let Ok((target_transform, _)) = targets.get(target) else {
+                    continue;
+                };
+                follow.update(target_transform.translation.truncate(), Vec2::ZERO, delta)
+            }
+        };
+
+        // the area follows window resizes and zoom, so clamp every frame and
+        // keep the clamped position to not lag behind when turning back
+        let bounds = bounds.map(|bounds| bounds.rect).or(level_bounds.0);
+        let clamped = !matches!(mode, CameraMode::FreePan { .. });
+        if let (true, Some(bounds), Some(projection)) = (clamped, bounds, projection) {
+            position = clamp_view(position, projection.area, bounds);
+            follow.position = Some(position);
+        }
+
+        // effects go on top of the stored position, so the follow never
+        // sees them and they can briefly show past the bounds
+        let (offset, roll) = shake.map_or((Vec2::ZERO, 0.), |shake| (shake.offset(), shake.roll()));
+        transform.translation = (position + offset).extend(transform.translation.z);
+        transform.rotation = Quat::from_rotation_z(roll);
+    }
+}
+
+/// States the camera systems can run in, any of the app's states.
+pub trait CameraState: States {}
+
+impl<T: States> CameraState for T {}
+
+/// Runs the cameras while the app is in `active`.
+pub struct CameraPlugin<State> {
+    active: State,
+}
+
+impl<State> CameraPlugin<State> {
+    pub fn new(active: State) -> CameraPlugin<State> {
+        CameraPlugin {
+            active,
+        }
+    }
+}
 
-impl Plugin for CameraPlugin {
+impl<State: CameraState> Plugin for CameraPlugin<State> {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_systems(Update, camera_movement.run_if(in_state(GameState::InGame)));
+        app
+            .init_resource::<LevelBounds>()
+            .init_resource::<CameraBindings>()
+            .configure_set(PostUpdate, CameraSet::Zoom
+                .before(CameraUpdateSystem)
+                .run_if(in_state(self.active.clone())))
+            .configure_set(PostUpdate, CameraSet::Follow
+                .after(CameraUpdateSystem)
+                .before(TransformSystem::TransformPropagate)
+                .run_if(in_state(self.active.clone())))
+            .add_systems(Update, (
+                zoom_input,
+                toggle_free_pan,
+                advance_camera_shake,
+            ).run_if(in_state(self.active.clone())))
+            .add_systems(PostUpdate, apply_zoom.in_set(CameraSet::Zoom))
+            .add_systems(PostUpdate, (
+                update_level_bounds,
+                camera_movement,
+            ).chain().in_set(CameraSet::Follow));
     }
 }
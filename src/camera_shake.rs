@@ -0,0 +1,250 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+
+#[derive(Clone, Copy, Debug)]
+struct Punch {
+    impulse: Vec2,
+    duration: f32,
+    elapsed: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct ZoomPulse {
+    amount: f32,
+    duration: f32,
+    elapsed: f32,
+}
+
+/// Shake, punches and zoom pulses layered on top of where the camera
+/// follow puts the view. The same seed and frame times give the same
+/// offsets, so runs can be compared.
+#[derive(Component, Clone, Debug)]
+pub struct CameraShake {
+    pub seed: u64,
+    /// Trauma lost per second.
+    pub decay: f32,
+    /// Offset at full trauma, in world units.
+    pub max_offset: f32,
+    /// Roll at full trauma, in radians.
+    pub max_roll: f32,
+    /// How fast the shake wobbles, in noise samples per second.
+    pub frequency: f32,
+    trauma: f32,
+    time: f32,
+    punches: Vec<Punch>,
+    pulses: Vec<ZoomPulse>,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            decay: 0.8,
+            max_offset: 24.,
+            max_roll: 0.05,
+            frequency: 15.,
+            trauma: 0.,
+            time: 0.,
+            punches: vec![],
+            pulses: vec![],
+        }
+    }
+}
+
+impl CameraShake {
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    /// Adds to the trauma, capped at 1. The shake grows with its square, so
+    /// small hits stay subtle.
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0., 1.);
+    }
+
+    /// Knocks the view by `impulse`, easing back over `duration` seconds.
+    pub fn punch(&mut self, impulse: Vec2, duration: f32) {
+        self.punches.push(Punch {
+            impulse,
+            duration: duration.max(f32::EPSILON),
+            elapsed: 0.,
+        });
+    }
+
+    /// Scales the view by up to `1 + amount` and back over `duration`
+    /// seconds, negative amounts zoom in. Needs a `CameraZoom` on the camera.
+    pub fn zoom_pulse(&mut self, amount: f32, duration: f32) {
+        self.pulses.push(ZoomPulse {
+            amount,
+            duration: duration.max(f32::EPSILON),
+            elapsed: 0.,
+        });
+    }
+
+    pub fn advance(&mut self, delta: f32) {
+        self.time += delta;
+        self.trauma = (self.trauma - self.decay * delta).max(0.);
+
+        for punch in self.punches.iter_mut() {
+            punch.elapsed += delta;
+        }
+        self.punches.retain(|punch| punch.elapsed < punch.duration);
+
+        for pulse in self.pulses.iter_mut() {
+            pulse.elapsed += delta;
+        }
+        self.pulses.retain(|pulse| pulse.elapsed < pulse.duration);
+    }
+
+    /// Offset to add to the view position.
+    pub fn offset(&self) -> Vec2 {
+        let shake = self.trauma * self.trauma * self.max_offset;
+        let t = self.time * self.frequency;
+        let noise = Vec2::new(noise(self.seed, 0, t), noise(self.seed, 1, t)) * shake;
+
+        let punches: Vec2 = self.punches.iter()
+            .map(|punch| {
+                let remaining = 1. - punch.elapsed / punch.duration;
+                punch.impulse * remaining * remaining
+            })
+            .sum();
+
+        noise + punches
+    }
+
+    /// Rotation around the view axis, in radians.
+    pub fn roll(&self) -> f32 {
+        self.trauma * self.trauma * self.max_roll * noise(self.seed, 2, self.time * self.frequency)
+    }
+
+    /// Takes the offset and roll back out of `point`, seen through a camera
+    /// shaken around `center`, giving where it is under the steady view.
+    pub fn unshake(&self, center: Vec2, point: Vec2) -> Vec2 {
+        center - self.offset() + Vec2::from_angle(-self.roll()).rotate(point - center)
+    }
+
+    /// Factor to multiply the projection scale by.
+    pub fn zoom_multiplier(&self) -> f32 {
+        self.pulses.iter()
+            .map(|pulse| 1. + pulse.amount * (PI * pulse.elapsed / pulse.duration).sin())
+            .product()
+    }
+}
+
+/// Random value in `[-1, 1]` for a whole number `index`, from splitmix64.
+fn hash(seed: u64, channel: u64, index: i64) -> f32 {
+    let mut x = seed
+        ^ channel.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (index as u64).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^= x >> 31;
+    (x >> 40) as f32 / (1u64 << 24) as f32 * 2. - 1.
+}
+
+/// Smooth value noise, interpolated between the hashes of whole numbers.
+fn noise(seed: u64, channel: u64, t: f32) -> f32 {
+    let index = t.floor();
+    let fraction = t - index;
+    let smooth = fraction * fraction * (3. - 2. * fraction);
+    let (a, b) = (hash(seed, channel, index as i64), hash(seed, channel, index as i64 + 1));
+    a + (b - a) * smooth
+}
+
+pub fn advance_camera_shake(
+    time: Res<Time>,
+    mut shakes: Query<&mut CameraShake>,
+) {
+    for mut shake in shakes.iter_mut() {
+        shake.advance(time.delta_seconds());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELTAS: [f32; 6] = [0.016, 0.017, 0.033, 0.008, 0.016, 0.1];
+
+    fn offsets(seed: u64) -> Vec<(Vec2, f32)> {
+        let mut shake = CameraShake::default().with_seed(seed);
+        shake.add_trauma(1.);
+        DELTAS.iter()
+            .map(|delta| {
+                shake.advance(*delta);
+                (shake.offset(), shake.roll())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_same_offsets() {
+        let first = offsets(7);
+        assert_eq!(first, offsets(7));
+        assert_ne!(first, offsets(8));
+        assert!(first.iter().all(|(offset, _)| offset.length() <= 24. * 2f32.sqrt()));
+    }
+
+    #[test]
+    fn trauma_decays_to_zero() {
+        let mut shake = CameraShake::default();
+        shake.add_trauma(0.5);
+        shake.add_trauma(0.8);
+        assert_eq!(shake.trauma(), 1.);
+
+        shake.advance(0.5);
+        assert!((shake.trauma() - 0.6).abs() < 1e-6);
+
+        // 1 / 0.8 seconds in total is enough
+        shake.advance(1.);
+        assert_eq!(shake.trauma(), 0.);
+        assert_eq!(shake.offset(), Vec2::ZERO);
+        assert_eq!(shake.roll(), 0.);
+    }
+
+    #[test]
+    fn punches_expire() {
+        let mut shake = CameraShake::default();
+        shake.punch(Vec2::new(10., 0.), 0.2);
+        assert_eq!(shake.offset(), Vec2::new(10., 0.));
+
+        shake.advance(0.1);
+        assert!(shake.offset().abs_diff_eq(Vec2::new(2.5, 0.), 1e-5));
+
+        shake.advance(0.1);
+        assert_eq!(shake.offset(), Vec2::ZERO);
+    }
+
+    #[test]
+    fn unshake_undoes_offset_and_roll() {
+        let mut shake = CameraShake::default().with_seed(3);
+        shake.add_trauma(1.);
+        shake.punch(Vec2::new(5., -3.), 0.5);
+        shake.advance(0.05);
+
+        // a point 10 units right of the steady center, as the shaken camera shows it
+        let steady = Vec2::new(100., 50.);
+        let center = steady + shake.offset();
+        let seen = center + Vec2::from_angle(shake.roll()).rotate(Vec2::new(10., 0.));
+        assert!(shake.unshake(center, seen).abs_diff_eq(steady + Vec2::new(10., 0.), 1e-4));
+    }
+
+    #[test]
+    fn zoom_pulses_expire() {
+        let mut shake = CameraShake::default();
+        shake.zoom_pulse(0.2, 0.4);
+        assert_eq!(shake.zoom_multiplier(), 1.);
+
+        shake.advance(0.2);
+        assert!((shake.zoom_multiplier() - 1.2).abs() < 1e-5);
+
+        shake.advance(0.2);
+        assert_eq!(shake.zoom_multiplier(), 1.);
+    }
+}
@@ -0,0 +1,125 @@
+use bevy::prelude::*;
+
+use crate::{
+    facing::Facing,
+    game::{GameState, Player},
+    input::WalkTo,
+    interaction::{Interactable, InteractionSet},
+    encumbrance::EncumbranceRules,
+    inventory::{Inventory, ItemStack, PickupRules, Reach},
+    items::ItemRegistry,
+    stats::Stats,
+};
+
+/// A world object with its own [`Inventory`] that opens a transfer window
+/// when clicked.
+#[derive(Component, Clone, Debug)]
+pub struct Container {
+    pub name: String,
+}
+
+/// Everything that makes an entity a container, apart from how it looks and
+/// collides.
+pub fn container_bundle(name: &str, inventory: Inventory) -> impl Bundle {
+    (
+        Container {
+            name: name.into(),
+        },
+        inventory,
+        Interactable {
+            label: name.into(),
+        },
+        Name::new(name.to_string()),
+    )
+}
+
+/// Container whose transfer window is open, if any.
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq, Deref)]
+pub struct OpenContainer(pub Option<Entity>);
+
+/// Moves as much of the stack in `slot` of `from` into `to` as fits, by
+/// slots and by weight.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct TransferStack {
+    pub from: Entity,
+    pub to: Entity,
+    pub slot: usize,
+}
+
+fn system_open_container(
+    mut commands: Commands,
+    mut reach: Reach,
+    containers: Query<&Transform, With<Container>>,
+    player_query: Query<(Entity, &Transform, &Facing, Option<&Stats>, Option<&WalkTo>), (With<Player>, Without<Container>)>,
+    mut open: ResMut<OpenContainer>,
+) {
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    let container = reach.reach_or_walk_to(&mut commands, player, |container| {
+        containers.get(container).ok().map(|transform| transform.translation.truncate())
+    });
+
+    if let Some(container) = container {
+        open.set_if_neq(OpenContainer(Some(container)));
+    }
+}
+
+fn system_close_distant_container(
+    rules: Res<PickupRules>,
+    containers: Query<&Transform, With<Container>>,
+    player_query: Query<&Transform, (With<Player>, Without<Container>)>,
+    mut open: ResMut<OpenContainer>,
+) {
+    let Some(container) = open.0 else {
+        return;
+    };
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+
+    let in_reach = containers.get(container).is_ok_and(|transform| {
+        player.translation.truncate().distance(transform.translation.truncate()) <= rules.max_distance
+    });
+    if !in_reach {
+        open.0 = None;
+    }
+}
+
+fn system_transfer_stacks(
+    mut events: EventReader<TransferStack>,
+    registry: Res<ItemRegistry>,
+    encumbrance_rules: Res<EncumbranceRules>,
+    mut inventories: Query<&mut Inventory>,
+) {
+    for event in events.iter() {
+        let Ok([mut from, mut to]) = inventories.get_many_mut([event.from, event.to]) else {
+            continue;
+        };
+        let Some(stack) = from.get(event.slot).cloned() else {
+            continue;
+        };
+
+        let carryable = to.fits_by_weight(&stack, encumbrance_rules.max_weight(&to), &registry);
+        let leftover = to.add(ItemStack::new(stack.item_id, carryable), &registry);
+        from.take(event.slot, carryable - leftover, &registry);
+    }
+}
+
+#[derive(Default)]
+pub struct ContainerPlugin;
+
+impl Plugin for ContainerPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<OpenContainer>()
+            .add_event::<TransferStack>()
+            .add_systems(FixedUpdate, system_open_container
+                .after(InteractionSet::Hover)
+                .run_if(in_state(GameState::InGame)))
+            .add_systems(Update, (
+                system_transfer_stacks,
+                system_close_distant_container,
+            ).run_if(in_state(GameState::InGame)));
+    }
+}
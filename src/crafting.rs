@@ -0,0 +1,277 @@
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+};
+use serde::Deserialize;
+
+use crate::{game::GameState, inventory::{Inventory, ItemStack}, items::ItemRegistry};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Recipe {
+    pub id: String,
+    pub name: String,
+    pub inputs: Vec<ItemStack>,
+    pub outputs: Vec<ItemStack>,
+    /// Kind of [`CraftingStation`] that has to be nearby, if any.
+    #[serde(default)]
+    pub station: Option<String>,
+    /// Seconds between starting and getting the outputs.
+    #[serde(default)]
+    pub craft_time: f32,
+}
+
+/// Recipes as read from a `.recipes.ron` file.
+#[derive(Debug, Deserialize, TypeUuid, TypePath)]
+#[uuid = "d41e8f0a-5c27-4b9e-8a63-1f2b7c9e0d58"]
+pub struct RecipeBook {
+    pub recipes: Vec<Recipe>,
+}
+
+#[derive(Default)]
+pub struct RecipeBookLoader;
+
+impl AssetLoader for RecipeBookLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let book: RecipeBook = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(book));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["recipes.ron"]
+    }
+}
+
+/// Every known recipe, in the order of the [`RecipeBook`].
+#[derive(Resource, Default)]
+pub struct RecipeRegistry {
+    recipes: Vec<Recipe>,
+}
+
+impl RecipeRegistry {
+    pub fn get(&self, id: &str) -> Option<&Recipe> {
+        self.recipes.iter().find(|recipe| recipe.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Recipe> {
+        self.recipes.iter()
+    }
+}
+
+fn sync_recipe_registry(
+    mut events: EventReader<AssetEvent<RecipeBook>>,
+    books: Res<Assets<RecipeBook>>,
+    mut registry: ResMut<RecipeRegistry>,
+) {
+    for event in events.iter() {
+        let (AssetEvent::Created { handle } | AssetEvent::Modified { handle }) = event else {
+            continue;
+        };
+        if let Some(book) = books.get(handle) {
+            registry.recipes = book.recipes.clone();
+        }
+    }
+}
+
+/// Something recipes can require to be close by, like a campfire.
+#[derive(Component, Clone, Debug)]
+pub struct CraftingStation {
+    pub kind: String,
+    /// How close the crafter has to stand.
+    pub radius: f32,
+}
+
+/// Whether a station of `kind` is close enough to `position`. Recipes
+/// without a station can be crafted anywhere.
+pub fn station_in_reach<'a>(
+    kind: Option<&str>,
+    position: Vec2,
+    mut stations: impl Iterator<Item = (&'a GlobalTransform, &'a CraftingStation)>,
+) -> bool {
+    let Some(kind) = kind else {
+        return true;
+    };
+
+    stations.any(|(transform, station)| {
+        station.kind == kind && transform.translation().truncate().distance(position) <= station.radius
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CraftError {
+    UnknownRecipe,
+    MissingInputs,
+    NoRoom,
+    NoStation,
+    Busy,
+}
+
+impl CraftError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            CraftError::UnknownRecipe => "Unknown recipe",
+            CraftError::MissingInputs => "Missing ingredients",
+            CraftError::NoRoom => "No room for the result",
+            CraftError::NoStation => "Needs a station nearby",
+            CraftError::Busy => "Already crafting",
+        }
+    }
+}
+
+/// Asks for `recipe` to be crafted from the `crafter`'s inventory.
+#[derive(Event, Clone, Debug)]
+pub struct CraftRequest {
+    pub crafter: Entity,
+    pub recipe: String,
+}
+
+#[derive(Event, Clone, Debug)]
+pub struct CraftStarted {
+    pub crafter: Entity,
+    pub recipe: String,
+}
+
+#[derive(Event, Clone, Debug)]
+pub struct CraftCompleted {
+    pub crafter: Entity,
+    pub recipe: String,
+}
+
+#[derive(Event, Clone, Debug)]
+pub struct CraftFailed {
+    pub crafter: Entity,
+    pub recipe: String,
+    pub reason: CraftError,
+}
+
+/// A craft in progress. Nothing is consumed until the timer runs out, so
+/// the inputs are checked again then.
+#[derive(Component, Clone, Debug)]
+pub struct Crafting {
+    pub recipe: String,
+    pub timer: Timer,
+}
+
+fn check_recipe(
+    recipe: &Recipe,
+    inventory: &Inventory,
+    position: Vec2,
+    registry: &ItemRegistry,
+    stations: &Query<(&GlobalTransform, &CraftingStation)>,
+) -> Result<(), CraftError> {
+    if !station_in_reach(recipe.station.as_deref(), position, stations.iter()) {
+        return Err(CraftError::NoStation);
+    }
+    inventory.can_craft(recipe, registry)
+}
+
+fn start_crafting(
+    mut commands: Commands,
+    mut requests: EventReader<CraftRequest>,
+    recipes: Res<RecipeRegistry>,
+    registry: Res<ItemRegistry>,
+    crafters: Query<(&GlobalTransform, &Inventory, Option<&Crafting>)>,
+    stations: Query<(&GlobalTransform, &CraftingStation)>,
+    mut started: EventWriter<CraftStarted>,
+    mut failed: EventWriter<CraftFailed>,
+) {
+    for request in requests.iter() {
+        let Ok((transform, inventory, crafting)) = crafters.get(request.crafter) else {
+            continue;
+        };
+
+        let result = (|| {
+            let recipe = recipes.get(&request.recipe).ok_or(CraftError::UnknownRecipe)?;
+            if crafting.is_some() {
+                return Err(CraftError::Busy);
+            }
+            check_recipe(recipe, inventory, transform.translation().truncate(), &registry, &stations)?;
+            Ok(recipe)
+        })();
+
+        match result {
+            Ok(recipe) => {
+                commands.entity(request.crafter).insert(Crafting {
+                    recipe: recipe.id.clone(),
+                    timer: Timer::from_seconds(recipe.craft_time, TimerMode::Once),
+                });
+                started.send(CraftStarted {
+                    crafter: request.crafter,
+                    recipe: recipe.id.clone(),
+                });
+            }
+            Err(reason) => failed.send(CraftFailed {
+                crafter: request.crafter,
+                recipe: request.recipe.clone(),
+                reason,
+            }),
+        }
+    }
+}
+
+fn finish_crafting(
+    mut commands: Commands,
+    time: Res<Time>,
+    recipes: Res<RecipeRegistry>,
+    registry: Res<ItemRegistry>,
+    mut crafters: Query<(Entity, &GlobalTransform, &mut Inventory, &mut Crafting)>,
+    stations: Query<(&GlobalTransform, &CraftingStation)>,
+    mut completed: EventWriter<CraftCompleted>,
+    mut failed: EventWriter<CraftFailed>,
+) {
+    for (crafter, transform, mut inventory, mut crafting) in crafters.iter_mut() {
+        if !crafting.timer.tick(time.delta()).finished() {
+            continue;
+        }
+        commands.entity(crafter).remove::<Crafting>();
+
+        let result = recipes.get(&crafting.recipe)
+            .ok_or(CraftError::UnknownRecipe)
+            .and_then(|recipe| {
+                check_recipe(recipe, &inventory, transform.translation().truncate(), &registry, &stations)?;
+                inventory.craft(recipe, &registry)
+            });
+
+        match result {
+            Ok(()) => completed.send(CraftCompleted {
+                crafter,
+                recipe: crafting.recipe.clone(),
+            }),
+            Err(reason) => failed.send(CraftFailed {
+                crafter,
+                recipe: crafting.recipe.clone(),
+                reason,
+            }),
+        }
+    }
+}
+
+/// Registers the recipe asset and runs crafts. Like the `ItemsPlugin`, it
+/// must be added before the `LoadingPlugin`.
+#[derive(Default)]
+pub struct CraftingPlugin;
+
+impl Plugin for CraftingPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_asset::<RecipeBook>()
+            .init_asset_loader::<RecipeBookLoader>()
+            .init_resource::<RecipeRegistry>()
+            .add_event::<CraftRequest>()
+            .add_event::<CraftStarted>()
+            .add_event::<CraftCompleted>()
+            .add_event::<CraftFailed>()
+            .add_systems(Update, sync_recipe_registry)
+            .add_systems(Update, (
+                start_crafting,
+                finish_crafting.after(start_crafting),
+            ).run_if(in_state(GameState::InGame)));
+    }
+}
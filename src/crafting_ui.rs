@@ -0,0 +1,258 @@
+use bevy::{prelude::*, ui::RelativeCursorPosition};
+
+use crate::{
+    crafting::{CraftCompleted, CraftFailed, CraftRequest, Crafting, CraftingStation, RecipeRegistry, station_in_reach},
+    game::{GameState, Player},
+    inventory::Inventory,
+    items::ItemRegistry,
+};
+
+const PANEL_WIDTH: f32 = 260.;
+const STATUS_SECONDS: f32 = 2.;
+
+const RECIPE_COLOR: Color = Color::rgba(0.65, 0.65, 0.65, 0.8);
+const RECIPE_HOVERED_COLOR: Color = Color::rgba(0.75, 0.75, 0.75, 0.9);
+const RECIPE_DISABLED_COLOR: Color = Color::rgba(0.25, 0.25, 0.25, 0.8);
+const TEXT_COLOR: Color = Color::WHITE;
+const TEXT_DISABLED_COLOR: Color = Color::GRAY;
+
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct CraftingBindings {
+    pub toggle_panel: KeyCode,
+}
+
+impl Default for CraftingBindings {
+    fn default() -> Self {
+        Self {
+            toggle_panel: KeyCode::C,
+        }
+    }
+}
+
+#[derive(Component)]
+struct CraftingPanelUi;
+
+#[derive(Component)]
+struct RecipeButtonUi(String);
+
+#[derive(Component)]
+struct CraftingStatusUi(Timer);
+
+fn text_style(font_size: f32) -> TextStyle {
+    TextStyle {
+        font_size,
+        color: TEXT_COLOR,
+        ..default()
+    }
+}
+
+/// Rebuilt whenever the recipes change, so edits to the recipe file show up
+/// without a restart.
+fn spawn_crafting_ui(
+    mut commands: Commands,
+    recipes: Res<RecipeRegistry>,
+    registry: Res<ItemRegistry>,
+    panel_q: Query<(Entity, &Visibility), With<CraftingPanelUi>>,
+) {
+    if !recipes.is_changed() && !panel_q.is_empty() {
+        return;
+    }
+
+    let mut visibility = Visibility::Hidden;
+    for (panel, panel_visibility) in panel_q.iter() {
+        visibility = *panel_visibility;
+        commands.entity(panel).despawn_recursive();
+    }
+
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(8.),
+                top: Val::Px(40.),
+                width: Val::Px(PANEL_WIDTH),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(8.)),
+                row_gap: Val::Px(4.),
+                ..default()
+            },
+            background_color: Color::rgba(0., 0., 0., 0.6).into(),
+            visibility,
+            ..default()
+        },
+        CraftingPanelUi,
+        RelativeCursorPosition::default(),
+    ))
+        .with_children(|builder| {
+            builder.spawn(TextBundle::from_section("Crafting", text_style(18.)));
+            builder.spawn((
+                TextBundle::from_section("", text_style(14.)),
+                CraftingStatusUi(Timer::from_seconds(STATUS_SECONDS, TimerMode::Once)),
+            ));
+
+            for recipe in recipes.iter() {
+                let inputs = recipe.inputs.iter()
+                    .map(|input| format!("{} {}", input.quantity, registry.name(&input.item_id)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let mut sections = vec![
+                    TextSection::new(recipe.name.clone(), text_style(16.)),
+                    TextSection::new(format!("\n{}", inputs), text_style(13.)),
+                ];
+                if let Some(station) = &recipe.station {
+                    sections.push(TextSection::new(format!(" at {}", station), text_style(13.)));
+                }
+
+                builder.spawn((
+                    ButtonBundle {
+                        style: Style {
+                            padding: UiRect::all(Val::Px(6.)),
+                            ..default()
+                        },
+                        background_color: RECIPE_COLOR.into(),
+                        ..default()
+                    },
+                    RecipeButtonUi(recipe.id.clone()),
+                ))
+                    .with_children(|builder| {
+                        builder.spawn(TextBundle::from_sections(sections));
+                    });
+            }
+        });
+}
+
+fn toggle_crafting_panel(
+    keyboard_input: Res<Input<KeyCode>>,
+    bindings: Res<CraftingBindings>,
+    mut panel_q: Query<&mut Visibility, With<CraftingPanelUi>>,
+) {
+    if !keyboard_input.just_pressed(bindings.toggle_panel) {
+        return;
+    }
+
+    for mut visibility in panel_q.iter_mut() {
+        *visibility = match *visibility {
+            Visibility::Hidden => Visibility::Visible,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+/// Greys out the recipes the player can't craft right now.
+fn update_recipe_buttons(
+    recipes: Res<RecipeRegistry>,
+    registry: Res<ItemRegistry>,
+    player_q: Query<(&GlobalTransform, &Inventory), With<Player>>,
+    stations: Query<(&GlobalTransform, &CraftingStation)>,
+    mut buttons: Query<(&RecipeButtonUi, &Interaction, &Children, &mut BackgroundColor)>,
+    mut texts: Query<&mut Text>,
+) {
+    let Ok((transform, inventory)) = player_q.get_single() else {
+        return;
+    };
+    let position = transform.translation().truncate();
+
+    for (button, interaction, children, mut background) in buttons.iter_mut() {
+        let craftable = recipes.get(&button.0).is_some_and(|recipe| {
+            station_in_reach(recipe.station.as_deref(), position, stations.iter())
+                && inventory.can_craft(recipe, &registry).is_ok()
+        });
+
+        *background = match (craftable, interaction) {
+            (false, _) => RECIPE_DISABLED_COLOR,
+            (true, Interaction::None) => RECIPE_COLOR,
+            (true, _) => RECIPE_HOVERED_COLOR,
+        }.into();
+
+        let color = match craftable {
+            true => TEXT_COLOR,
+            false => TEXT_DISABLED_COLOR,
+        };
+        for child in children.iter() {
+            let Ok(mut text) = texts.get_mut(*child) else {
+                continue;
+            };
+            for section in text.sections.iter_mut() {
+                if section.style.color != color {
+                    section.style.color = color;
+                }
+            }
+        }
+    }
+}
+
+fn recipe_button_clicks(
+    player_q: Query<Entity, With<Player>>,
+    interactions: Query<(&RecipeButtonUi, &Interaction), Changed<Interaction>>,
+    mut requests: EventWriter<CraftRequest>,
+) {
+    let Ok(player) = player_q.get_single() else {
+        return;
+    };
+
+    for (button, interaction) in interactions.iter() {
+        if *interaction == Interaction::Pressed {
+            requests.send(CraftRequest {
+                crafter: player,
+                recipe: button.0.clone(),
+            });
+        }
+    }
+}
+
+fn crafting_status(
+    time: Res<Time>,
+    recipes: Res<RecipeRegistry>,
+    player_q: Query<(Entity, Option<&Crafting>), With<Player>>,
+    mut completed: EventReader<CraftCompleted>,
+    mut failed: EventReader<CraftFailed>,
+    mut status_q: Query<(&mut Text, &mut CraftingStatusUi)>,
+) {
+    let Ok((player, crafting)) = player_q.get_single() else {
+        return;
+    };
+    let Ok((mut text, mut status)) = status_q.get_single_mut() else {
+        return;
+    };
+    let name = |id: &str| recipes.get(id).map_or(id.to_string(), |recipe| recipe.name.clone());
+
+    if let Some(event) = completed.iter().filter(|event| event.crafter == player).last() {
+        text.sections[0].value = format!("Crafted {}", name(&event.recipe));
+        status.0.reset();
+    }
+    if let Some(event) = failed.iter().filter(|event| event.crafter == player).last() {
+        text.sections[0].value = event.reason.message().into();
+        status.0.reset();
+    }
+
+    if let Some(crafting) = crafting {
+        text.sections[0].value = format!(
+            "Crafting {}... {:.0}%",
+            name(&crafting.recipe),
+            crafting.timer.percent() * 100.,
+        );
+        status.0.reset();
+        return;
+    }
+
+    if status.0.tick(time.delta()).just_finished() {
+        text.sections[0].value.clear();
+    }
+}
+
+#[derive(Default)]
+pub struct CraftingUiPlugin;
+
+impl Plugin for CraftingUiPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<CraftingBindings>()
+            .add_systems(Update, (
+                spawn_crafting_ui,
+                toggle_crafting_panel,
+                update_recipe_buttons,
+                recipe_button_clicks,
+                crafting_status,
+            ).run_if(in_state(GameState::InGame)));
+    }
+}
@@ -0,0 +1,138 @@
+use bevy::prelude::*;
+
+use crate::{game::GameState, inventory::Inventory};
+
+#[derive(Clone, Debug)]
+pub struct EncumbranceTier {
+    pub name: String,
+    /// Load, as a fraction of the carry capacity, above which the tier applies.
+    pub load: f32,
+    pub speed_multiplier: f32,
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct EncumbranceRules {
+    /// Sorted by `load`, the heaviest tier reached applies.
+    pub tiers: Vec<EncumbranceTier>,
+    /// Pickups that would take the load over this are refused.
+    pub max_load: f32,
+}
+
+impl Default for EncumbranceRules {
+    fn default() -> Self {
+        Self {
+            tiers: vec![
+                EncumbranceTier {
+                    name: "Burdened".into(),
+                    load: 1.,
+                    speed_multiplier: 0.75,
+                },
+                EncumbranceTier {
+                    name: "Overloaded".into(),
+                    load: 1.25,
+                    speed_multiplier: 0.4,
+                },
+            ],
+            max_load: 1.5,
+        }
+    }
+}
+
+impl EncumbranceRules {
+    pub fn tier(&self, load: f32) -> Option<usize> {
+        self.tiers.iter().rposition(|tier| load > tier.load)
+    }
+
+    /// Heaviest total weight `inventory` may reach through pickups.
+    pub fn max_weight(&self, inventory: &Inventory) -> f32 {
+        inventory.carry_capacity * self.max_load
+    }
+}
+
+/// How much an entity's inventory slows it down, kept in sync with its
+/// [`Inventory`].
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct Encumbrance {
+    /// Index into [`EncumbranceRules::tiers`].
+    pub tier: Option<usize>,
+    pub speed_multiplier: f32,
+}
+
+impl Default for Encumbrance {
+    fn default() -> Self {
+        Self {
+            tier: None,
+            speed_multiplier: 1.,
+        }
+    }
+}
+
+fn update_encumbrance(
+    rules: Res<EncumbranceRules>,
+    mut query: Query<(Ref<Inventory>, &mut Encumbrance)>,
+) {
+    for (inventory, mut encumbrance) in query.iter_mut() {
+        if !(inventory.is_changed() || rules.is_changed()) {
+            continue;
+        }
+
+        let tier = rules.tier(inventory.load());
+        encumbrance.set_if_neq(Encumbrance {
+            tier,
+            speed_multiplier: tier.map_or(1., |tier| rules.tiers[tier].speed_multiplier),
+        });
+    }
+}
+
+#[derive(Default)]
+pub struct EncumbrancePlugin;
+
+impl Plugin for EncumbrancePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<EncumbranceRules>()
+            .add_systems(Update, update_encumbrance.run_if(in_state(GameState::InGame)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{inventory::ItemStack, items::test_registry};
+
+    #[test]
+    fn tiers_start_above_their_load() {
+        let rules = EncumbranceRules::default();
+        assert_eq!(rules.tier(0.), None);
+        assert_eq!(rules.tier(1.), None);
+        assert_eq!(rules.tier(1.01), Some(0));
+        assert_eq!(rules.tier(1.25), Some(0));
+        assert_eq!(rules.tier(1.26), Some(1));
+        assert_eq!(rules.tier(1.5), Some(1));
+        assert_eq!(rules.tier(3.), Some(1));
+    }
+
+    #[test]
+    fn speed_follows_the_load() {
+        let registry = test_registry();
+        let mut app = App::new();
+        app
+            .init_resource::<EncumbranceRules>()
+            .add_systems(Update, update_encumbrance);
+        let entity = app.world.spawn((
+            Inventory::new(5).with_carry_capacity(10.),
+            Encumbrance::default(),
+        )).id();
+
+        // stones weigh 2, so each one adds 0.2 to the load
+        for (stones, tier, speed_multiplier) in [(5, None, 1.), (6, Some(0), 0.75), (7, Some(1), 0.4)] {
+            let mut inventory = app.world.get_mut::<Inventory>(entity).unwrap();
+            let missing = stones - inventory.count(&"stone".into());
+            inventory.add(ItemStack::new("stone", missing), &registry);
+            app.update();
+
+            let encumbrance = app.world.get::<Encumbrance>(entity).unwrap();
+            assert_eq!(*encumbrance, Encumbrance { tier, speed_multiplier }, "{stones} stones");
+        }
+    }
+}
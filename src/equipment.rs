@@ -0,0 +1,28 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::items::{EquipmentSlot, ItemId};
+
+/// What an entity is wearing or holding, one item per slot.
+#[derive(Component, Default, Debug)]
+pub struct Equipment {
+    slots: HashMap<EquipmentSlot, ItemId>,
+}
+
+impl Equipment {
+    pub fn get(&self, slot: EquipmentSlot) -> Option<&ItemId> {
+        self.slots.get(&slot)
+    }
+
+    /// Puts the item in the slot and returns whatever was there before.
+    pub fn equip(&mut self, slot: EquipmentSlot, item_id: ItemId) -> Option<ItemId> {
+        self.slots.insert(slot, item_id)
+    }
+
+    pub fn unequip(&mut self, slot: EquipmentSlot) -> Option<ItemId> {
+        self.slots.remove(&slot)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&EquipmentSlot, &ItemId)> {
+        self.slots.iter()
+    }
+}
@@ -0,0 +1,56 @@
+use bevy::prelude::*;
+
+use crate::game::GameState;
+
+/// Direction an entity looks in, as an angle in radians from +x. Kept apart
+/// from the [`Transform`] so aiming never rotates the physics body; anything
+/// can write it, be it the mouse, a gamepad, AI or a cutscene.
+#[derive(Component, Clone, Copy, Default, Debug, PartialEq)]
+pub struct Facing(pub f32);
+
+impl Facing {
+    pub fn from_direction(direction: Vec2) -> Self {
+        Facing(direction.y.atan2(direction.x))
+    }
+
+    pub fn angle(&self) -> f32 {
+        self.0
+    }
+
+    pub fn direction(&self) -> Vec2 {
+        Vec2::from_angle(self.0)
+    }
+
+    pub fn rotation(&self) -> Quat {
+        Quat::from_rotation_z(self.0)
+    }
+}
+
+/// Rotates a child to match its parent's [`Facing`]. `offset` is added for
+/// art that isn't drawn looking along +x.
+#[derive(Component, Clone, Copy, Default, Debug)]
+pub struct FollowFacing {
+    pub offset: f32,
+}
+
+fn follow_parent_facing(
+    parents: Query<(&Facing, &Children), Changed<Facing>>,
+    mut children: Query<(&FollowFacing, &mut Transform)>,
+) {
+    for (facing, parent_children) in parents.iter() {
+        for child in parent_children.iter() {
+            if let Ok((follow, mut transform)) = children.get_mut(*child) {
+                transform.rotation = Quat::from_rotation_z(facing.angle() + follow.offset);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct FacingPlugin;
+
+impl Plugin for FacingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, follow_parent_facing.run_if(in_state(GameState::InGame)));
+    }
+}
@@ -20,13 +20,14 @@ use bevy::{
         renderer::{RenderContext, RenderDevice},
         texture::BevyDefault,
         view::{ExtractedView, ViewTarget, RenderLayers},
-        RenderApp, extract_resource::{ExtractResource, ExtractResourcePlugin}, camera::RenderTarget, render_asset::RenderAssets, mesh::{Indices, VertexFormatSize},
+        RenderApp, extract_resource::{ExtractResource, ExtractResourcePlugin}, camera::{RenderTarget, CameraUpdateSystem}, render_asset::RenderAssets, mesh::{Indices, VertexFormatSize},
     },
     sprite::{MaterialMesh2dBundle, Mesh2dHandle}, window::WindowResized,
+    transform::TransformSystem,
 };
-use bevy_rapier2d::prelude::{RapierContext, QueryFilter};
+use bevy_rapier2d::prelude::{RapierContext, QueryFilter, CollisionGroups, Group};
 
-use crate::game::{GameState, setup_player, Player};
+use crate::{game::{GameState, setup_player, Player, MainCamera}, facing::Facing, stats::{Stats, StatBlock}, camera::CameraSet};
 
 #[derive(Resource, Clone, Deref, ExtractResource)]
 struct FieldOfViewImage(Handle<Image>);
@@ -99,12 +100,46 @@ fn vision_cone_texture_setup(
 #[derive(Component, Default, Clone, Copy, ExtractComponent)]
 pub struct FovMarker;
 
-const FOV_VIEW_DISTANCE: f32 = 500.;
+pub const FOV_VIEW_DISTANCE: f32 = 500.;
 const FOV_INTENSITY: f32 = 0.8;
-const FOV_ANGLE: f32 = 1.2;
+pub const FOV_ANGLE: f32 = 1.2;
 const FOV_STEPS: u32 = 1000;
 const FOV_VERTEX_COUNT: usize = 1001;
 
+/// Renders the visibility mask. Has to see exactly what the main camera sees.
+#[derive(Component)]
+struct FovCamera;
+
+fn fov_camera_follow_main(
+    main_camera: Query<&Transform, (With<MainCamera>, Without<FovCamera>)>,
+    mut fov_camera: Query<&mut Transform, With<FovCamera>>,
+) {
+    let Ok(main_transform) = main_camera.get_single() else {
+        return;
+    };
+
+    for mut transform in fov_camera.iter_mut() {
+        transform.set_if_neq(*main_transform);
+    }
+}
+
+/// Runs before bevy recomputes the projections, so both cameras cover the
+/// same area in the frame the zoom changes.
+fn fov_camera_match_zoom(
+    main_camera: Query<&OrthographicProjection, (With<MainCamera>, Without<FovCamera>)>,
+    mut fov_camera: Query<&mut OrthographicProjection, With<FovCamera>>,
+) {
+    let Ok(main_projection) = main_camera.get_single() else {
+        return;
+    };
+
+    for mut projection in fov_camera.iter_mut() {
+        if projection.scale != main_projection.scale {
+            projection.scale = main_projection.scale;
+        }
+    }
+}
+
 fn camera_setup(
     mut commands: Commands,
     fov_image: Res<FieldOfViewImage>,
@@ -126,6 +161,7 @@ fn camera_setup(
             ..default()
         },
         first_pass_layer,
+        FovCamera,
     ));
 
     // Using this to test if the rendered fov texture is correct
@@ -140,19 +176,19 @@ fn camera_setup(
 
 fn fov_mesh_setup(
     mut commands: Commands,
-    query: Query<(Entity, &Transform), With<Player>>,
+    query: Query<(Entity, &Transform, &Facing), With<Player>>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
     let increment = (FOV_ANGLE * 2.) / FOV_STEPS as f32;
 
-    let Ok((entity, transform)) = query.get_single() else {
+    let Ok((entity, transform, facing)) = query.get_single() else {
         return;
     };
 
     let mut fov_mesh = Mesh::new(PrimitiveTopology::TriangleList);
 
-    let mut angle_sweeper = transform.clone();
+    let mut angle_sweeper = Transform::from_rotation(facing.rotation());
     angle_sweeper.rotate_z(FOV_ANGLE + increment);
 
     let origin = transform.translation.truncate();
@@ -193,7 +229,7 @@ fn fov_mesh_setup(
 }
 
 fn fov_mesh_update(
-    query: Query<(&Transform, Entity), (With<Player>, Without<FovMesh>)>,
+    query: Query<(&Transform, &Facing, Option<&Stats>, Entity), (With<Player>, Without<FovMesh>)>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut mesh_handle: Query<(&Mesh2dHandle, &mut Transform), (With<FovMesh>, Without<Player>)>,
     rapier_context: Res<RapierContext>,
@@ -203,22 +239,26 @@ fn fov_mesh_update(
         return;
     };
 
-    let increment = (FOV_ANGLE * 2.) / FOV_STEPS as f32;
-
-    let Ok((transform, entity)) = query.get_single() else {
+    let Ok((transform, facing, stats, entity)) = query.get_single() else {
         return;
     };
 
+    let (fov_range, fov_angle) = stats.map_or(
+        (FOV_VIEW_DISTANCE, FOV_ANGLE),
+        |stats| (stats.fov_range, stats.fov_angle),
+    );
+    let increment = (fov_angle * 2.) / FOV_STEPS as f32;
+
     let origin = transform.translation.truncate();
-    let mut angle_sweeper = transform.clone();
-    angle_sweeper.rotate_z(FOV_ANGLE + increment);
+    let mut angle_sweeper = Transform::from_rotation(facing.rotation());
+    angle_sweeper.rotate_z(fov_angle + increment);
 
     let mut fov_mesh = Mesh::new(PrimitiveTopology::TriangleList);
 
     let uvs: Vec<[f32; 2]> = vec![[1., 1.]; FOV_VERTEX_COUNT];
     let normals: Vec<[f32; 3]> = vec![[0., 0., 1.]; FOV_VERTEX_COUNT];
     let mut v_pos = vec![[0., 0., 1.0]];
-    let filter = QueryFilter::new().exclude_collider(entity);
+    let filter = sight_filter().exclude_collider(entity);
 
     for _step in 0..FOV_STEPS {
         angle_sweeper.rotate_z(-increment);
@@ -228,13 +268,13 @@ fn fov_mesh_update(
         if let Some((_entity, toi)) = rapier_context.cast_ray(
                 origin,
                 vertex_direction.normalize(),
-                FOV_VIEW_DISTANCE,
+                fov_range,
                 false,
                 filter,
         ) {
             hit_point = vertex_direction * toi;
         } else {
-            hit_point =  vertex_direction * FOV_VIEW_DISTANCE;
+            hit_point =  vertex_direction * fov_range;
         }
 
         //gizmos.line_2d(
@@ -264,45 +304,127 @@ fn fov_mesh_update(
 #[derive(Component)]
 struct FovMesh;
 
+/// Circle lit around the player by light sources, drawn into the same
+/// visibility mask as the vision cone.
+#[derive(Component)]
+struct FovLight;
+
+fn fov_light_setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.spawn((
+        MaterialMesh2dBundle {
+            mesh: meshes.add(shape::Circle::new(1.).into()).into(),
+            material: materials.add(ColorMaterial::from(Color::WHITE)),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        Name::new("fov light"),
+        RenderLayers::layer(1),
+        FovLight,
+    ));
+}
+
+fn fov_light_update(
+    player_query: Query<(&Transform, &Stats), (With<Player>, Without<FovLight>)>,
+    mut light_query: Query<(&mut Transform, &mut Visibility), (With<FovLight>, Without<Player>)>,
+) {
+    let (Ok((player_transform, stats)), Ok((mut transform, mut visibility))) =
+        (player_query.get_single(), light_query.get_single_mut()) else {
+        return;
+    };
+
+    if stats.light_radius <= 0. {
+        *visibility = Visibility::Hidden;
+        return;
+    }
+
+    *visibility = Visibility::Visible;
+    transform.translation = player_transform.translation.truncate().extend(1.);
+    transform.scale = Vec3::new(stats.light_radius, stats.light_radius, 1.);
+}
+
+/// Colliders in this group block sight.
+pub const OCCLUDER_GROUP: Group = Group::GROUP_3;
+
+/// Ray filter hitting only what blocks sight.
+fn sight_filter<'a>() -> QueryFilter<'a> {
+    QueryFilter::new().groups(CollisionGroups::new(Group::ALL, OCCLUDER_GROUP))
+}
+
+/// Whether `viewer` can see `target`: lit by its light or inside its vision
+/// cone, with no occluder in between. Matches what the FOV mesh shows.
+/// Colliders for which `ignore` returns true don't block, e.g. the target's own.
+pub fn is_visible(
+    rapier_context: &RapierContext,
+    viewer: Entity,
+    origin: Vec2,
+    facing: &Facing,
+    stats: &StatBlock,
+    target: Vec2,
+    ignore: impl Fn(Entity) -> bool,
+) -> bool {
+    let offset = target - origin;
+    let distance = offset.length();
+    if distance <= f32::EPSILON {
+        return true;
+    }
+
+    let lit = distance <= stats.light_radius;
+    let in_cone = distance <= stats.fov_range
+        && facing.direction().angle_between(offset).abs() <= stats.fov_angle;
+    if !(lit || in_cone) {
+        return false;
+    }
+
+    let blocks = |entity: Entity| !ignore(entity);
+    let filter = sight_filter()
+        .exclude_collider(viewer)
+        .predicate(&blocks);
+    rapier_context
+        .cast_ray(origin, offset / distance, distance, true, filter)
+        .is_none()
+}
+
 
 pub fn vision_cone_gizmo(
     mut gizmos: Gizmos,
-    query: Query<&Transform, With<Player>>,
+    query: Query<(&Transform, &Facing, Option<&Stats>), With<Player>>,
     rapier_context: Res<RapierContext>,
 ) {
-    if let Ok(transform) = query.get_single() {
+    if let Ok((transform, facing, stats)) = query.get_single() {
+        let fov_angle = stats.map_or(FOV_ANGLE, |stats| stats.fov_angle);
+        let origin = transform.translation.truncate();
         if let Some((_entity, toi)) = rapier_context.cast_ray(
-            transform.translation.truncate(),
-            transform.right().truncate().normalize(),
+            origin,
+            facing.direction(),
             500.,
             false,
-            QueryFilter::default(),
+            sight_filter(),
         ) {
             gizmos.ray_2d(
-                transform.translation.truncate(),
-                transform.right().truncate().normalize() * toi,
+                origin,
+                facing.direction() * toi,
                 Color::GREEN
             );
         } else {
             gizmos.ray_2d(
-                transform.translation.truncate(),
-                transform.right().truncate() * 500.,
+                origin,
+                facing.direction() * 500.,
                 Color::GREEN
             );
         }
 
-        let mut ccw_transform = transform.clone();
-        ccw_transform.rotate_z(FOV_ANGLE);
-        let mut cw_transform = transform.clone();
-        cw_transform.rotate_z(-FOV_ANGLE);
         gizmos.ray_2d(
-            transform.translation.truncate(),
-            ccw_transform.right().truncate() * 500.,
+            origin,
+            Vec2::from_angle(facing.angle() + fov_angle) * 500.,
             Color::GREEN
         );
         gizmos.ray_2d(
-            transform.translation.truncate(),
-            cw_transform.right().truncate() * 500.,
+            origin,
+            Vec2::from_angle(facing.angle() - fov_angle) * 500.,
             Color::GREEN
         );
     }
@@ -322,12 +444,22 @@ impl Plugin for FieldOfViewPlugin {
                 apply_deferred,
                 camera_setup.after(vision_cone_texture_setup),
                 fov_mesh_setup.after(setup_player),
+                fov_light_setup,
             ).chain())
             .add_systems(Update, (
                 window_resized_update_texture_size,
                 vision_cone_gizmo,
                 fov_mesh_update,
-            ).run_if(in_state(GameState::InGame)));
+                fov_light_update,
+            ).run_if(in_state(GameState::InGame)))
+            .add_systems(PostUpdate, (
+                fov_camera_match_zoom
+                    .after(CameraSet::Zoom)
+                    .before(CameraUpdateSystem),
+                fov_camera_follow_main
+                    .after(CameraSet::Follow)
+                    .before(TransformSystem::TransformPropagate),
+            ));
 
         let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
             return;
@@ -1,7 +1,7 @@
 use bevy::{prelude::*, utils::HashMap, core_pipeline::clear_color::ClearColorConfig};
-use bevy_rapier2d::prelude::{Collider, KinematicCharacterController};
+use bevy_rapier2d::prelude::{Collider, KinematicCharacterController, QueryFilterFlags};
 
-use crate::{loading::{LoadingPlugin, GameAssets}, mouse::MousePlugin, input::{MovementPlugin, Velocity}, camera::CameraPlugin, animator::{AnimationKey, Animator, animation_selection}, animation::{SpriteSheetAnimation, AnimationPlugin}, field_of_view::{FovMarker, FieldOfViewPlugin}, scene::setup_scene, inventory::{InventoryPlugin, Inventory}, };
+use crate::{loading::{LoadingPlugin, GameAssets}, mouse::MousePlugin, input::{MovementPlugin, Velocity, PlayerControls}, camera::{CameraPlugin, CameraFollow, CameraZoom, CameraMode}, camera_shake::CameraShake, animator::{AnimationKey, Animator, animation_selection}, animation::{SpriteSheetAnimation, AnimationPlugin}, field_of_view::{FovMarker, FieldOfViewPlugin}, scene::setup_scene, inventory::{InventoryPlugin, Inventory}, inventory_ui::InventoryUiPlugin, replay::ReplayPlugin, facing::{FacingPlugin, Facing, FollowFacing}, interaction::InteractionPlugin, items::ItemsPlugin, stats::{StatsPlugin, BaseStats, Stats}, loot::LootPlugin, level::{LevelPlugin, Level, PLAYER_SPAWN}, auto_collider::AutoColliderPlugin, sorting::{SortingPlugin, SortingLayer, YSort}, container::ContainerPlugin, crafting::CraftingPlugin, crafting_ui::CraftingUiPlugin, encumbrance::EncumbrancePlugin, };
 
 use std::{f32::consts::TAU, fmt::{Display, Formatter, Result}};
 
@@ -26,17 +26,32 @@ impl Plugin for GamePlugin {
         app
             .add_state::<GameState>()
             .add_plugins((
+                ItemsPlugin,
+                LootPlugin,
+                CraftingPlugin,
+                LevelPlugin,
+                SortingPlugin,
                 LoadingPlugin::new(GameState::Loading, GameState::InGame),
-                MousePlugin,
+            ))
+            .add_plugins((
+                MousePlugin::new(GameState::InGame),
                 MovementPlugin,
-                CameraPlugin,
+                ReplayPlugin,
+                FacingPlugin,
+                CameraPlugin::new(GameState::InGame),
                 AnimationPlugin,
                 FieldOfViewPlugin,
                 InventoryPlugin,
+                InventoryUiPlugin,
+                InteractionPlugin,
+                StatsPlugin,
+                ContainerPlugin,
+                CraftingUiPlugin,
+                EncumbrancePlugin,
+                AutoColliderPlugin,
             ))
             .add_systems(OnEnter(GameState::InGame),
                 (
-                    setup_background,
                     setup_scene,
                     setup_player,
                 ).chain()
@@ -81,6 +96,7 @@ fn animation_selector(data: &AnimationData) -> Animations {
 pub fn setup_player(
     mut commands: Commands,
     game_assets: Res<GameAssets>,
+    levels: Res<Assets<Level>>,
     mut texture_atlases: ResMut<Assets<TextureAtlas>>,
     mut animations: ResMut<Assets<SpriteSheetAnimation>>,
 ) {
@@ -92,9 +108,17 @@ pub fn setup_player(
             ..default()
         },
         MainCamera,
+        CameraFollow::default(),
+        CameraMode::default(),
+        CameraZoom::default(),
+        CameraShake::default(),
         FovMarker,
     ));
 
+    let spawn = levels.get(&game_assets.level)
+        .and_then(|level| level.spawn_point(PLAYER_SPAWN))
+        .unwrap_or_default();
+
     let texture_handle = game_assets.player_spritesheet.clone();
     let texture_atlas = TextureAtlas::from_grid(texture_handle, Vec2::new(64.0, 64.0), 11, 1, None, None);
     let texture_atlas_handle = texture_atlases.add(texture_atlas);
@@ -119,14 +143,25 @@ pub fn setup_player(
     commands
         .spawn((
             SpatialBundle::from_transform(
-                Transform::from_translation(Vec3::splat(1.)),
+                Transform::from_translation(spawn.extend(1.)),
             ),
             Player,
             Name::new("Player Entity"),
             animator,
             AnimationData::default(),
+            Velocity::default(),
+            Facing::default(),
+            PlayerControls::default(),
+            BaseStats::default(),
+            Stats::default(),
             Collider::ball(15.),
-            KinematicCharacterController::default(),
+            SortingLayer::Characters,
+            YSort { pivot: -15. },
+            // walk over items and through interaction areas
+            KinematicCharacterController {
+                filter_flags: QueryFilterFlags::EXCLUDE_SENSORS,
+                ..default()
+            },
         ))
         .with_children(|parent| {
             parent
@@ -135,25 +170,14 @@ pub fn setup_player(
                     transform: Transform::from_rotation(Quat::from_rotation_z(0.25 * TAU)),
                     ..Default::default()
                 })
-                .insert(player.clone());
+                .insert((
+                    player.clone(),
+                    FollowFacing { offset: 0.25 * TAU },
+                ));
         });
 }
 
-fn setup_background(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-) {
-    commands.spawn((
-        SpriteBundle {
-            texture: asset_server.load("background/campsite-improved.png"),
-            transform: Transform::from_scale(Vec3::splat(0.5)),
-            ..Default::default()
-        },
-        Name::new("bg"),
-    ));
-}
-
-fn update_animation_data(mut query: Query<(&Velocity, &mut AnimationData)>) {
+fn update_animation_data(mut query: Query<(&Velocity, &mut AnimationData), Changed<Velocity>>) {
     for (velocity, mut anim_data) in query.iter_mut() {
         anim_data.moving = velocity.length() > 0.0;
     }
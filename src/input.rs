@@ -3,14 +3,67 @@ use bevy::{
     math::{Vec2, Vec3},
     prelude::*, gizmos,
 };
-use bevy_rapier2d::prelude::{RapierContext, Collider, QueryFilter, KinematicCharacterController};
+use bevy_rapier2d::{
+    plugin::PhysicsSet,
+    prelude::{RapierContext, RapierConfiguration, RapierPhysicsPlugin, NoUserData, TimestepMode, Collider, QueryFilter, KinematicCharacterController},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{game::{Player, GameState}, mouse::CursorWorldPosition, stats::Stats, encumbrance::Encumbrance};
+
+pub const SPEED: f32 = 100.0;
+pub const FIXED_TIMESTEP: f32 = 1. / 60.;
+
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub enum InputSet {
+    Tick,
+    /// Fills [`ActionInput`] for the current fixed tick, either from the
+    /// devices or from a replay.
+    Sample,
+    /// Moves the player from [`ActionInput`], ahead of the physics step.
+    Move,
+}
+
+/// Where [`ActionInput`] comes from each fixed tick.
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputSource {
+    #[default]
+    Live,
+    Replay,
+}
 
-use crate::game::{Player, GameState};
+/// Number of fixed ticks run since entering the game.
+#[derive(Resource, Default, Clone, Copy, Debug, Deref)]
+pub struct FixedTick(pub u64);
 
-const SPEED: f32 = 100.0;
+/// Everything gameplay needs from the player for one fixed tick. Systems that
+/// must replay deterministically read this instead of the raw input resources.
+#[derive(Resource, Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ActionInput {
+    /// Movement direction per [`PlayerControls::slot`].
+    pub movement: Vec<Vec2>,
+    /// Cursor position in world space.
+    pub aim: Option<Vec2>,
+    /// Primary button was pressed since the previous tick.
+    pub primary: bool,
+}
+
+impl ActionInput {
+    pub fn movement(&self, slot: usize) -> Vec2 {
+        self.movement.get(slot).copied().unwrap_or_default()
+    }
+}
 
-#[derive(Component)]
-pub struct Velocity(Vec2);
+/// Clicks seen since the last fixed tick, so a press is not lost on frames
+/// that run no tick or counted twice on frames that run several.
+#[derive(Resource, Default)]
+struct PendingClicks {
+    primary: bool,
+}
+
+#[derive(Component, Clone, Copy, Default, Debug, PartialEq)]
+pub struct Velocity(pub Vec2);
 
 impl Velocity {
     pub fn length(&self) -> f32 {
@@ -18,32 +71,131 @@ impl Velocity {
     }
 }
 
-fn player_controller(
-    mut commands: Commands,
+/// Keys that drive one controllable entity. Give each local player its own
+/// set to run several of them side by side.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct PlayerControls {
+    pub slot: usize,
+    pub up: KeyCode,
+    pub down: KeyCode,
+    pub left: KeyCode,
+    pub right: KeyCode,
+}
+
+impl Default for PlayerControls {
+    fn default() -> Self {
+        Self {
+            slot: 0,
+            up: KeyCode::W,
+            down: KeyCode::S,
+            left: KeyCode::A,
+            right: KeyCode::D,
+        }
+    }
+}
+
+/// Steers the entity towards `target` until it is within `range`. Giving any
+/// movement input of its own cancels the walk.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct WalkTo {
+    pub target: Entity,
+    pub range: f32,
+}
+
+impl PlayerControls {
+    fn direction(&self, keyboard_input: &Input<KeyCode>) -> Vec2 {
+        let mut vec2 = Vec2::default();
+        if keyboard_input.pressed(self.up) {
+            vec2.y = 1.0;
+        } else if keyboard_input.pressed(self.down) {
+            vec2.y = -1.0;
+        }
+
+        if keyboard_input.pressed(self.right) {
+            vec2.x = 1.0;
+        } else if keyboard_input.pressed(self.left) {
+            vec2.x = -1.0;
+        }
+
+        vec2
+    }
+}
+
+fn latch_clicks(
+    mouse: Res<Input<MouseButton>>,
+    interactions: Query<&Interaction>,
+    mut clicks: ResMut<PendingClicks>,
+) {
+    // clicks on the UI don't reach the world
+    if interactions.iter().any(|interaction| *interaction != Interaction::None) {
+        return;
+    }
+
+    clicks.primary |= mouse.just_pressed(MouseButton::Left);
+}
+
+fn advance_fixed_tick(mut tick: ResMut<FixedTick>) {
+    tick.0 += 1;
+}
+
+pub fn live_input(source: Res<InputSource>) -> bool {
+    *source == InputSource::Live
+}
+
+fn sample_action_input(
     keyboard_input: Res<Input<KeyCode>>,
-    query: Query<Entity, With<Player>>,
+    mut clicks: ResMut<PendingClicks>,
+    cursor_world_position: Res<CursorWorldPosition>,
+    controls_query: Query<&PlayerControls>,
+    mut action_input: ResMut<ActionInput>,
 ) {
-    let mut vec2 = Vec2::default();
-    if keyboard_input.pressed(KeyCode::W) {
-        vec2.y = 1.0;
-    } else if keyboard_input.pressed(KeyCode::S) {
-        vec2.y = -1.0;
-    } else {
-        vec2.y = 0.0;
+    let mut movement = vec![];
+    for controls in controls_query.iter() {
+        if movement.len() <= controls.slot {
+            movement.resize(controls.slot + 1, Vec2::ZERO);
+        }
+        movement[controls.slot] = controls.direction(&keyboard_input);
     }
 
-    if keyboard_input.pressed(KeyCode::D) {
-        vec2.x = 1.0;
-    } else if keyboard_input.pressed(KeyCode::A) {
-        vec2.x = -1.0;
-    } else {
-        vec2.x = 0.0;
+    *action_input = ActionInput {
+        movement,
+        aim: cursor_world_position.0,
+        primary: std::mem::take(&mut clicks.primary),
+    };
+}
+
+fn player_controller(
+    action_input: Res<ActionInput>,
+    mut query: Query<(&PlayerControls, &mut Velocity), With<Player>>,
+) {
+    for (controls, mut velocity) in query.iter_mut() {
+        velocity.set_if_neq(Velocity(action_input.movement(controls.slot)));
     }
+}
 
-    let velocity = Velocity(vec2);
+fn walk_to_target(
+    mut commands: Commands,
+    action_input: Res<ActionInput>,
+    targets: Query<&Transform>,
+    mut walkers: Query<(Entity, &Transform, &WalkTo, &mut Velocity, Option<&PlayerControls>)>,
+) {
+    for (entity, transform, walk_to, mut velocity, controls) in walkers.iter_mut() {
+        let taken_over = controls.is_some_and(|controls| action_input.movement(controls.slot) != Vec2::ZERO);
+        let Some(target) = targets.get(walk_to.target).ok().filter(|_| !taken_over) else {
+            commands.entity(entity).remove::<WalkTo>();
+            continue;
+        };
 
-    let entity = query.single();
-    commands.entity(entity).insert(velocity);
+        // stop once in range and leave the component for whoever asked for
+        // the walk to notice the arrival
+        let offset = (target.translation - transform.translation).truncate();
+        let direction = if offset.length() <= walk_to.range {
+            Vec2::ZERO
+        } else {
+            offset.normalize()
+        };
+        velocity.set_if_neq(Velocity(direction));
+    }
 }
 
 // @deprecated because I couldn't handle "corner" cases XD
@@ -122,16 +274,29 @@ fn system_manual_player_movement(
 }
 
 fn system_kinematic_movement(
-    mut controllers: Query<(&mut KinematicCharacterController, &Velocity), With<Player>>,
-    time: Res<Time>,
+    mut controllers: Query<(&mut KinematicCharacterController, &Velocity, Option<&Stats>, Option<&Encumbrance>), With<Player>>,
+    fixed_time: Res<FixedTime>,
 ) {
-    let Ok((mut controller, velocity)) = controllers.get_single_mut() else {
-        return;
-    };
+    let delta = fixed_time.period.as_secs_f32();
+    for (mut controller, velocity, stats, encumbrance) in controllers.iter_mut() {
+        let speed = stats.map_or(SPEED, |stats| stats.speed)
+            * encumbrance.map_or(1., |encumbrance| encumbrance.speed_multiplier);
+        // Add onto whatever rapier hasn't consumed yet, e.g. when physics
+        // isn't running.
+        let pending = controller.translation.unwrap_or_default();
+        controller.translation = Some(pending + velocity.0 * speed * delta);
+    }
+}
 
-    let delta = time.delta_seconds();
-    let velocity = velocity.0 * SPEED * delta;
-    controller.translation = Some(velocity);
+/// Runs the fixed schedule every `timestep` seconds and has rapier advance by
+/// that much per step. Rapier only steps once per fixed tick when its systems
+/// run in `FixedUpdate`, see [`FixedPhysicsPlugin`].
+pub fn set_fixed_timestep(app: &mut App, timestep: f32) {
+    app.insert_resource(FixedTime::new_from_secs(timestep));
+    app.world.get_resource_or_insert_with(RapierConfiguration::default).timestep_mode = TimestepMode::Fixed {
+        dt: timestep,
+        substeps: 1,
+    };
 }
 
 #[derive(Default)]
@@ -139,13 +304,57 @@ pub struct MovementPlugin;
 
 impl Plugin for MovementPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            Update,
-            (
-                player_controller,
-                //system_manual_player_movement,
-                system_kinematic_movement,
-            ).run_if(in_state(GameState::InGame))
-        );
+        set_fixed_timestep(app, FIXED_TIMESTEP);
+        app
+            .init_resource::<InputSource>()
+            .init_resource::<FixedTick>()
+            .init_resource::<ActionInput>()
+            .init_resource::<PendingClicks>()
+            .configure_sets(FixedUpdate, (
+                InputSet::Tick,
+                InputSet::Sample,
+                InputSet::Move,
+            ).chain().run_if(in_state(GameState::InGame)))
+            .add_systems(PreUpdate, latch_clicks.after(bevy::input::InputSystem).after(bevy::ui::UiSystem::Focus))
+            .add_systems(FixedUpdate, (
+                advance_fixed_tick.in_set(InputSet::Tick),
+                sample_action_input.run_if(live_input).in_set(InputSet::Sample),
+            ))
+            .add_systems(
+                FixedUpdate,
+                (
+                    player_controller,
+                    walk_to_target,
+                    //system_manual_player_movement,
+                    system_kinematic_movement,
+                ).chain().in_set(InputSet::Move)
+            );
+    }
+}
+
+/// Steps rapier in `FixedUpdate` right after the player moves, so physics
+/// advances once per fixed tick rather than once per rendered frame. Rapier
+/// has to be added with `with_default_system_setup(false)`.
+pub struct FixedPhysicsPlugin;
+
+impl Plugin for FixedPhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .configure_sets(FixedUpdate, (
+                PhysicsSet::SyncBackend,
+                PhysicsSet::SyncBackendFlush,
+                PhysicsSet::StepSimulation,
+                PhysicsSet::Writeback,
+            ).chain().after(InputSet::Move))
+            .add_systems(FixedUpdate, (
+                RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::SyncBackend)
+                    .in_set(PhysicsSet::SyncBackend),
+                RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::SyncBackendFlush)
+                    .in_set(PhysicsSet::SyncBackendFlush),
+                RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::StepSimulation)
+                    .in_set(PhysicsSet::StepSimulation),
+                RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::Writeback)
+                    .in_set(PhysicsSet::Writeback),
+            ));
     }
 }
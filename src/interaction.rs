@@ -0,0 +1,144 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::{Collider, QueryFilter, RapierContext};
+
+use crate::{game::GameState, input::{ActionInput, InputSet}, sorting::SortingLayer};
+
+const HIGHLIGHT_COLOR: Color = Color::YELLOW;
+const LABEL_OFFSET: f32 = 30.;
+
+/// Something the player can point at and click. The `label` is shown above
+/// it while hovered.
+#[derive(Component, Clone, Debug)]
+pub struct Interactable {
+    pub label: String,
+}
+
+/// The [`Interactable`] under the aim position this tick, if any.
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq, Deref)]
+pub struct HoveredEntity(pub Option<Entity>);
+
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub enum InteractionSet {
+    Hover,
+}
+
+#[derive(Component)]
+struct HoverLabel;
+
+fn update_hovered(
+    action_input: Res<ActionInput>,
+    rapier_context: Res<RapierContext>,
+    interactables: Query<(), With<Interactable>>,
+    parents: Query<&Parent>,
+    mut hovered: ResMut<HoveredEntity>,
+) {
+    let mut found = None;
+    if let Some(point) = action_input.aim {
+        rapier_context.intersections_with_point(point, QueryFilter::new(), |entity| {
+            // colliders are often children of the thing they belong to
+            let target = Some(entity)
+                .filter(|entity| interactables.contains(*entity))
+                .or_else(|| parents.get(entity).ok()
+                    .map(|parent| parent.get())
+                    .filter(|parent| interactables.contains(*parent)));
+
+            found = target;
+            found.is_none()
+        });
+    }
+
+    hovered.set_if_neq(HoveredEntity(found));
+}
+
+fn setup_hover_label(mut commands: Commands) {
+    commands.spawn((
+        Text2dBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: 16.0,
+                    color: Color::WHITE,
+                    ..default()
+                },
+            ).with_alignment(TextAlignment::Center),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        HoverLabel,
+        Name::new("Hover label"),
+    ));
+}
+
+fn update_hover_label(
+    hovered: Res<HoveredEntity>,
+    interactables: Query<(&Interactable, &GlobalTransform)>,
+    mut label_query: Query<(&mut Text, &mut Transform, &mut Visibility), With<HoverLabel>>,
+) {
+    let Ok((mut text, mut transform, mut visibility)) = label_query.get_single_mut() else {
+        return;
+    };
+
+    let Some((interactable, global_transform)) = hovered.and_then(|entity| interactables.get(entity).ok()) else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+
+    if text.sections[0].value != interactable.label {
+        text.sections[0].value = interactable.label.clone();
+    }
+    let position = global_transform.translation();
+    transform.translation = Vec3::new(position.x, position.y + LABEL_OFFSET, SortingLayer::Overhead.z());
+    *visibility = Visibility::Visible;
+}
+
+fn highlight_hovered(
+    hovered: Res<HoveredEntity>,
+    interactables: Query<(&GlobalTransform, Option<&Collider>, Option<&Children>), With<Interactable>>,
+    colliders: Query<(&GlobalTransform, &Collider)>,
+    mut gizmos: Gizmos,
+) {
+    let Some((global_transform, collider, children)) = hovered.and_then(|entity| interactables.get(entity).ok()) else {
+        return;
+    };
+
+    let mut outline = |transform: &GlobalTransform, collider: &Collider| {
+        let aabb = collider.raw.compute_local_aabb();
+        let center = transform.transform_point(Vec3::new(aabb.center().x, aabb.center().y, 0.));
+        let half_extents = aabb.half_extents();
+        let (_, rotation, _) = transform.to_scale_rotation_translation();
+        gizmos.rect_2d(
+            center.truncate(),
+            rotation.to_euler(EulerRot::ZYX).0,
+            Vec2::new(half_extents.x, half_extents.y) * 2.,
+            HIGHLIGHT_COLOR,
+        );
+    };
+
+    if let Some(collider) = collider {
+        outline(global_transform, collider);
+    }
+    for child in children.into_iter().flatten() {
+        if let Ok((transform, collider)) = colliders.get(*child) {
+            outline(transform, collider);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct InteractionPlugin;
+
+impl Plugin for InteractionPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<HoveredEntity>()
+            .configure_set(FixedUpdate, InteractionSet::Hover.after(InputSet::Sample))
+            .add_systems(FixedUpdate, update_hovered
+                .in_set(InteractionSet::Hover)
+                .run_if(in_state(GameState::InGame)))
+            .add_systems(OnEnter(GameState::InGame), setup_hover_label)
+            .add_systems(Update, (
+                update_hover_label,
+                highlight_hovered,
+            ).run_if(in_state(GameState::InGame)));
+    }
+}
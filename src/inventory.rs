@@ -1,184 +1,846 @@
-use bevy::prelude::*;
-use bevy_rapier2d::{prelude::{RapierContext, QueryFilter, CollisionGroups, Group}, rapier::prelude::InteractionGroups};
+use bevy::{prelude::*, ecs::system::SystemParam};
+use bevy_rapier2d::prelude::{Collider, CollisionGroups, Group, RapierContext, Sensor};
+use serde::Deserialize;
 
-use crate::game::{MainCamera, Player, GameState, setup_player};
+use crate::{game::{Player, GameState, setup_player}, input::{ActionInput, WalkTo}, facing::Facing, field_of_view::is_visible, stats::Stats, interaction::{HoveredEntity, InteractionSet, Interactable}, items::{ItemId, ItemRegistry, InventoryItemType, ConsumableStats, EquipmentSlot}, equipment::Equipment, crafting::{CraftError, Recipe}, encumbrance::{Encumbrance, EncumbranceRules}, sorting::SortingLayer};
 
-#[derive(Clone, Copy, Debug)]
-pub enum InventoryItemType {
-    Consumable,
-    Equipment,
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct ItemStack {
+    pub item_id: ItemId,
+    pub quantity: u32,
 }
 
-#[derive(Clone, Debug)]
-pub struct Item {
-    pub name: String,
-    pub item_type: InventoryItemType,
+impl ItemStack {
+    pub fn new(item_id: impl Into<ItemId>, quantity: u32) -> Self {
+        Self {
+            item_id: item_id.into(),
+            quantity,
+        }
+    }
 }
 
 #[derive(Component)]
 pub struct ItemOnGround {
-    pub item: Item,
+    pub stack: ItemStack,
 }
 
-#[derive(Default, Component)]
-pub struct Inventory {
-    pub items: Vec<Item>,
-    pub inventory_size: usize,
-}
-
-#[derive(Component)]
-struct InventoryUi;
-
-#[derive(Component)]
-struct InventoryItemUi;
+const ITEM_ON_GROUND_SIZE: f32 = 30.;
 
-fn system_setup_inventory(
-    mut commands: Commands,
-    player_q: Query<Entity, With<Player>>,
-) {
-    let Ok(entity) = player_q.get_single() else {
-        println!("Player not found");
-        return;
+/// Spawns a stack lying in the world, ready to be hovered and picked up.
+pub fn spawn_item_on_ground(
+    commands: &mut Commands,
+    registry: &ItemRegistry,
+    stack: ItemStack,
+    position: Vec2,
+) -> Entity {
+    let definition = registry.get(&stack.item_id);
+    let (texture, color) = match definition.and_then(|definition| definition.icon_image.clone()) {
+        Some(icon) => (icon, Color::WHITE),
+        None => (default(), Color::LIME_GREEN),
     };
 
-    commands.entity(entity).insert(Inventory::default());
-
-    commands.spawn((
-        NodeBundle {
-            style: Style {
-                width: Val::Percent(100.),
-                height: Val::Px(100.),
-                position_type: PositionType::Absolute,
-                bottom: Val::Px(0.),
-                left: Val::Px(0.),
-                justify_content: JustifyContent::Center,
-
-                display: Display::Grid,
-                grid_template_columns: vec![
-                    GridTrack::min_content(),
-                    GridTrack::min_content(),
-                    GridTrack::min_content(),
-                ],
-                grid_template_rows: vec![GridTrack::auto()],
+    commands
+        .spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::splat(ITEM_ON_GROUND_SIZE)),
+                    ..default()
+                },
+                texture,
+                transform: Transform::from_translation(position.extend(1.)),
                 ..default()
             },
-            ..default()
-        },
-        InventoryUi,
-    ))
-        .with_children(|builder| {
-            spawn_inventory_item(builder, "Nothing");
-            spawn_inventory_item(builder, "Nothing");
-            spawn_inventory_item(builder, "Nothing");
-        });
+            Interactable {
+                label: registry.name(&stack.item_id).into(),
+            },
+            Name::new(format!("{} on ground", registry.name(&stack.item_id))),
+            SortingLayer::Ground,
+            ItemOnGround { stack },
+            Collider::cuboid(ITEM_ON_GROUND_SIZE / 2., ITEM_ON_GROUND_SIZE / 2.),
+            CollisionGroups::new(Group::GROUP_2, Group::ALL),
+            Sensor,
+        ))
+        .id()
 }
 
-fn spawn_inventory_item(builder: &mut ChildBuilder, text: &str) {
-    builder.spawn((
-        NodeBundle {
-            style: Style {
-                display: Display::Grid,
-                width: Val::Px(100.),
-                height: Val::Px(100.),
-                ..default()
-            },
-            background_color: Color::rgb(0.65, 0.65, 0.65).into(),
-            ..default()
-        },
-        InventoryItemUi,
-    ))
-        .with_children(|builder| {
-            spawn_nested_text_bundle(builder, text);
-        });
+const PLAYER_INVENTORY_SIZE: usize = 15;
+const PLAYER_CARRY_CAPACITY: f32 = 10.;
+
+/// A fixed number of slots, each empty or holding one stack. The total
+/// weight is kept up to date by every method that moves items in or out.
+/// Equipped items are worn rather than carried, so they leave the inventory
+/// and stop counting towards its weight.
+#[derive(Component, Clone, Debug)]
+pub struct Inventory {
+    slots: Vec<Option<ItemStack>>,
+    /// Weight that can be carried without being slowed down.
+    pub carry_capacity: f32,
+    total_weight: f32,
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self {
+            slots: vec![],
+            carry_capacity: f32::INFINITY,
+            total_weight: 0.,
+        }
+    }
 }
 
-fn spawn_nested_text_bundle(builder: &mut ChildBuilder, text: &str) {
-    builder.spawn(TextBundle::from_section(
-        text,
-        TextStyle {
-            font_size: 24.0,
-            color: Color::BLACK,
+impl Inventory {
+    pub fn new(inventory_size: usize) -> Self {
+        Self {
+            slots: vec![None; inventory_size],
             ..default()
-        },
-    ));
+        }
+    }
+
+    pub fn with_carry_capacity(mut self, carry_capacity: f32) -> Self {
+        self.carry_capacity = carry_capacity;
+        self
+    }
+
+    pub fn total_weight(&self) -> f32 {
+        self.total_weight
+    }
+
+    /// Total weight as a fraction of the carry capacity.
+    pub fn load(&self) -> f32 {
+        self.total_weight / self.carry_capacity
+    }
+
+    /// How many of `stack` could be added before the total weight goes over
+    /// `max_weight`. Space in the slots is not taken into account.
+    pub fn fits_by_weight(&self, stack: &ItemStack, max_weight: f32, registry: &ItemRegistry) -> u32 {
+        let weight = registry.weight(&stack.item_id);
+        if weight <= 0. {
+            return stack.quantity;
+        }
+        let room = ((max_weight - self.total_weight) / weight).floor().max(0.);
+        stack.quantity.min(room as u32)
+    }
+
+    /// Sums the weight from scratch, for when item weights change.
+    pub fn recompute_weight(&mut self, registry: &ItemRegistry) {
+        self.total_weight = self.slots.iter()
+            .flatten()
+            .map(|stack| registry.weight(&stack.item_id) * stack.quantity as f32)
+            .sum();
+    }
+
+    fn change_weight(&mut self, item_id: &ItemId, quantity: i64, registry: &ItemRegistry) {
+        self.total_weight = (self.total_weight + registry.weight(item_id) * quantity as f32).max(0.);
+    }
+
+    pub fn inventory_size(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.slots.iter().all(Option::is_some)
+    }
+
+    pub fn get(&self, slot: usize) -> Option<&ItemStack> {
+        self.slots.get(slot).and_then(Option::as_ref)
+    }
+
+    pub fn count(&self, item_id: &ItemId) -> u32 {
+        self.slots.iter()
+            .flatten()
+            .filter(|stack| stack.item_id == *item_id)
+            .map(|stack| stack.quantity)
+            .sum()
+    }
+
+    /// Tops up existing stacks of the same item first, then fills empty
+    /// slots. Returns the quantity that did not fit.
+    pub fn add(&mut self, stack: ItemStack, registry: &ItemRegistry) -> u32 {
+        let max_stack = registry.max_stack(&stack.item_id);
+        let mut remaining = stack.quantity;
+
+        for existing in self.slots.iter_mut().flatten() {
+            if remaining == 0 {
+                break;
+            }
+            if existing.item_id == stack.item_id && existing.quantity < max_stack {
+                let moved = remaining.min(max_stack - existing.quantity);
+                existing.quantity += moved;
+                remaining -= moved;
+            }
+        }
+
+        for slot in self.slots.iter_mut().filter(|slot| slot.is_none()) {
+            if remaining == 0 {
+                break;
+            }
+            let moved = remaining.min(max_stack);
+            *slot = Some(ItemStack::new(stack.item_id.clone(), moved));
+            remaining -= moved;
+        }
+
+        self.change_weight(&stack.item_id, (stack.quantity - remaining) as i64, registry);
+        remaining
+    }
+
+    /// Removes up to `quantity` of an item, emptying the last stacks first.
+    /// Returns how many were removed.
+    pub fn remove(&mut self, item_id: &ItemId, quantity: u32, registry: &ItemRegistry) -> u32 {
+        let mut remaining = quantity;
+        for slot in self.slots.iter_mut().rev() {
+            if remaining == 0 {
+                break;
+            }
+            let Some(stack) = slot.as_mut().filter(|stack| stack.item_id == *item_id) else {
+                continue;
+            };
+            let taken = remaining.min(stack.quantity);
+            stack.quantity -= taken;
+            remaining -= taken;
+            if stack.quantity == 0 {
+                *slot = None;
+            }
+        }
+
+        let removed = quantity - remaining;
+        self.change_weight(item_id, -(removed as i64), registry);
+        removed
+    }
+
+    /// Takes up to `quantity` out of a single slot.
+    pub fn take(&mut self, slot: usize, quantity: u32, registry: &ItemRegistry) -> Option<ItemStack> {
+        let taken = self.take_within(slot, quantity)?;
+        self.change_weight(&taken.item_id, -(taken.quantity as i64), registry);
+        Some(taken)
+    }
+
+    /// Like [`Inventory::take`] for stacks that stay in this inventory, so
+    /// the weight doesn't change.
+    fn take_within(&mut self, slot: usize, quantity: u32) -> Option<ItemStack> {
+        let stack = self.slots.get_mut(slot)?.as_mut()?;
+        let taken = quantity.min(stack.quantity);
+        stack.quantity -= taken;
+        let item_id = stack.item_id.clone();
+        if stack.quantity == 0 {
+            self.slots[slot] = None;
+        }
+
+        (taken > 0).then(|| ItemStack::new(item_id, taken))
+    }
+
+    /// Moves `quantity` out of a stack into the first empty slot.
+    pub fn split(&mut self, slot: usize, quantity: u32) -> bool {
+        let Some(stack) = self.get(slot) else {
+            return false;
+        };
+        if quantity == 0 || quantity >= stack.quantity {
+            return false;
+        }
+        let Some(empty) = self.slots.iter().position(Option::is_none) else {
+            return false;
+        };
+
+        self.slots[empty] = self.take_within(slot, quantity);
+        true
+    }
+
+    /// Merges `from` onto `to` when they hold the same item, otherwise swaps
+    /// the two slots.
+    pub fn move_slot(&mut self, from: usize, to: usize, registry: &ItemRegistry) {
+        if from == to || from >= self.slots.len() || to >= self.slots.len() {
+            return;
+        }
+        if !self.merge(from, to, registry) {
+            self.slots.swap(from, to);
+        }
+    }
+
+    /// Moves as much of `from` onto `to` as fits when both hold the same
+    /// item. Returns false when nothing could be moved.
+    pub fn merge(&mut self, from: usize, to: usize, registry: &ItemRegistry) -> bool {
+        if from == to {
+            return false;
+        }
+        let (Some(source), Some(target)) = (self.get(from), self.get(to)) else {
+            return false;
+        };
+        if source.item_id != target.item_id {
+            return false;
+        }
+
+        let room = registry.max_stack(&target.item_id).saturating_sub(target.quantity);
+        let moved = room.min(source.quantity);
+        if moved == 0 {
+            return false;
+        }
+
+        self.take_within(from, moved);
+        if let Some(target) = self.slots[to].as_mut() {
+            target.quantity += moved;
+        }
+        true
+    }
+
+    /// Checks that `recipe` could be crafted right now, without changing
+    /// anything.
+    pub fn can_craft(&self, recipe: &Recipe, registry: &ItemRegistry) -> Result<(), CraftError> {
+        self.crafted(recipe, registry).map(|_| ())
+    }
+
+    /// Consumes the inputs of `recipe` and adds its outputs, or leaves the
+    /// inventory untouched if either part can't be done in full.
+    pub fn craft(&mut self, recipe: &Recipe, registry: &ItemRegistry) -> Result<(), CraftError> {
+        *self = self.crafted(recipe, registry)?;
+        Ok(())
+    }
+
+    fn crafted(&self, recipe: &Recipe, registry: &ItemRegistry) -> Result<Inventory, CraftError> {
+        let mut result = self.clone();
+        for input in recipe.inputs.iter() {
+            if result.remove(&input.item_id, input.quantity, registry) < input.quantity {
+                return Err(CraftError::MissingInputs);
+            }
+        }
+        // outputs may go into the slots the inputs just freed
+        for output in recipe.outputs.iter() {
+            if result.add(output.clone(), registry) > 0 {
+                return Err(CraftError::NoRoom);
+            }
+        }
+
+        Ok(result)
+    }
 }
 
-fn system_update_inventory(
-    inventory_q: Query<&Children, With<InventoryItemUi>>,
-    mut text_q: Query<&mut Text>,
-    player_q: Query<&Inventory, With<Player>>,
-) {
-    let Ok(inventory) = player_q.get_single() else {
-        return;
-    };
-    
-    for (index, children) in inventory_q.iter().enumerate() {
-        if let Some(item) = inventory.items.get(index) {
-            if let Some(text) = children.get(0) {
-                if let Ok(mut text) = text_q.get_mut(*text) {
-                    text.sections[0].value = item.name[..].into();
-                }
+/// Walks stop a bit short of `max_distance` so the pickup can't miss by a
+/// rounding error.
+pub const PICKUP_WALK_MARGIN: f32 = 0.8;
+
+/// When a clicked item may be picked up.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct PickupRules {
+    /// Farthest an item can be from the player's center.
+    pub max_distance: f32,
+    /// The item must be lit or inside the vision cone, and not behind a wall.
+    pub require_visible: bool,
+    /// Walk up to items clicked out of reach instead of refusing them.
+    pub auto_walk: bool,
+}
+
+impl Default for PickupRules {
+    fn default() -> Self {
+        Self {
+            max_distance: 60.,
+            require_visible: true,
+            auto_walk: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PickupFailReason {
+    Full,
+    TooFar,
+    NotVisible,
+    TooHeavy,
+}
+
+impl PickupFailReason {
+    pub fn message(&self) -> &'static str {
+        match self {
+            PickupFailReason::Full => "Inventory is full",
+            PickupFailReason::TooFar => "Too far away",
+            PickupFailReason::NotVisible => "Can't see that",
+            PickupFailReason::TooHeavy => "Too heavy to carry",
+        }
+    }
+}
+
+/// Sent when an item was clicked but stays on the ground.
+#[derive(Event, Clone, Debug)]
+pub struct PickupFailed {
+    pub picker: Entity,
+    pub item: Entity,
+    pub reason: PickupFailReason,
+}
+
+/// Decides whether the player can reach what they clicked, for picking items
+/// up and opening containers alike.
+#[derive(SystemParam)]
+pub struct Reach<'w, 's> {
+    action_input: Res<'w, ActionInput>,
+    hovered: Res<'w, HoveredEntity>,
+    rules: Res<'w, PickupRules>,
+    rapier_context: Res<'w, RapierContext>,
+    parents: Query<'w, 's, &'static Parent>,
+    pickup_failed: EventWriter<'w, PickupFailed>,
+}
+
+impl Reach<'_, '_> {
+    /// The clicked target, or the one the player is walking to, once it is in
+    /// reach and visible. `position` finds anything that counts as a target.
+    /// Clicks out of reach start a walk when the rules allow it, and the walk
+    /// is cleared on arrival.
+    pub fn reach_or_walk_to(
+        &mut self,
+        commands: &mut Commands,
+        player: (Entity, &Transform, &Facing, Option<&Stats>, Option<&WalkTo>),
+        position: impl Fn(Entity) -> Option<Vec2>,
+    ) -> Option<Entity> {
+        let (player, transform, facing, stats, walk_to) = player;
+        let origin = transform.translation.truncate();
+
+        // a fresh click wins over a target the player is still walking to
+        let clicked = self.hovered.0
+            .filter(|_| self.action_input.primary)
+            .filter(|target| position(*target).is_some());
+        let target = match (clicked, walk_to) {
+            (Some(target), _) => target,
+            (None, Some(walk_to)) if position(walk_to.target).is_some() => walk_to.target,
+            _ => return None,
+        };
+        let target_position = position(target)?;
+
+        if origin.distance(target_position) > self.rules.max_distance {
+            if clicked.is_none() {
+                // still on the way
+                return None;
+            }
+            if self.rules.auto_walk {
+                commands.entity(player).insert(WalkTo {
+                    target,
+                    range: self.rules.max_distance * PICKUP_WALK_MARGIN,
+                });
+            } else {
+                self.fail(player, target, PickupFailReason::TooFar);
             }
+            return None;
+        }
+
+        if walk_to.is_some() {
+            commands.entity(player).remove::<WalkTo>();
+        }
+
+        // the target's own colliders don't hide it
+        let own_collider = |entity: Entity| entity == target
+            || self.parents.get(entity).is_ok_and(|parent| parent.get() == target);
+        let stats = stats.map_or_else(default, |stats| stats.0);
+        if self.rules.require_visible && !is_visible(&self.rapier_context, player, origin, facing, &stats, target_position, own_collider) {
+            self.fail(player, target, PickupFailReason::NotVisible);
+            return None;
         }
+
+        Some(target)
+    }
+
+    pub fn fail(&mut self, picker: Entity, item: Entity, reason: PickupFailReason) {
+        self.pickup_failed.send(PickupFailed {
+            picker,
+            item,
+            reason,
+        });
     }
 }
 
-fn system_inventory_pickup(
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InventoryActionKind {
+    Drop,
+    Use,
+    Equip,
+}
+
+/// Asks for something to be done with the stack in `slot` of the `actor`'s
+/// inventory. The outcome is announced with one of the events below.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct InventoryAction {
+    pub actor: Entity,
+    pub slot: usize,
+    pub kind: InventoryActionKind,
+}
+
+#[derive(Event, Clone, Debug)]
+pub struct ItemDropped {
+    pub actor: Entity,
+    pub stack: ItemStack,
+    pub entity: Entity,
+}
+
+/// A consumable was used. Effects hook in here.
+#[derive(Event, Clone, Debug)]
+pub struct ItemUsed {
+    pub actor: Entity,
+    pub item_id: ItemId,
+    pub stats: ConsumableStats,
+}
+
+#[derive(Event, Clone, Debug)]
+pub struct ItemEquipped {
+    pub actor: Entity,
+    pub item_id: ItemId,
+    pub slot: EquipmentSlot,
+    pub replaced: Option<ItemId>,
+}
+
+fn system_setup_inventory(
     mut commands: Commands,
-    windows: Query<&Window>,
-    cam_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
-    mouse: Res<Input<MouseButton>>,
-    item_query: Query<(Entity, &Transform, &ItemOnGround), Without<Player>>,
-    mut player_query: Query<(Entity, &Transform, &mut Inventory), With<Player>>,
-    rapier_context: Res<RapierContext>,
+    player_q: Query<Entity, With<Player>>,
 ) {
-    if !mouse.just_pressed(MouseButton::Left) {
+    let Ok(entity) = player_q.get_single() else {
+        println!("Player not found");
         return;
-    }
+    };
 
-    (|| {
-        let wnd = windows.single();
+    commands.entity(entity).insert((
+        Inventory::new(PLAYER_INVENTORY_SIZE).with_carry_capacity(PLAYER_CARRY_CAPACITY),
+        Encumbrance::default(),
+        Equipment::default(),
+    ));
+}
 
-        let (camera, camera_transform) = cam_query.get_single().ok()?;
+fn system_inventory_pickup(
+    mut commands: Commands,
+    mut reach: Reach,
+    encumbrance_rules: Res<EncumbranceRules>,
+    registry: Res<ItemRegistry>,
+    mut item_query: Query<(&mut ItemOnGround, &Transform), Without<Player>>,
+    mut player_query: Query<(Entity, &Transform, &Facing, Option<&Stats>, &mut Inventory, Option<&WalkTo>), With<Player>>,
+) {
+    (|| {
+        let (player, transform, facing, stats, mut inventory, walk_to) = player_query.get_single_mut().ok()?;
+        let item = reach.reach_or_walk_to(&mut commands, (player, transform, facing, stats, walk_to), |item| {
+            item_query.get(item).ok().map(|(_, transform)| transform.translation.truncate())
+        })?;
+        let (mut item_on_ground, _) = item_query.get_mut(item).ok()?;
 
-        let mouse_pos_2d = wnd.cursor_position()
-            .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor))?;
+        let stack = item_on_ground.stack.clone();
+        let max_weight = encumbrance_rules.max_weight(&inventory);
+        let carryable = inventory.fits_by_weight(&stack, max_weight, &registry);
+        if carryable == 0 {
+            reach.fail(player, item, PickupFailReason::TooHeavy);
+            return None;
+        }
 
-        let (player, player_transform, mut inventory) = player_query.single_mut();
+        let leftover = stack.quantity - carryable
+            + inventory.add(ItemStack::new(stack.item_id, carryable), &registry);
+        if leftover == stack.quantity {
+            reach.fail(player, item, PickupFailReason::Full);
+            return None;
+        }
 
-        let filter = QueryFilter::new().groups(
-            CollisionGroups::new(Group::GROUP_2, Group::GROUP_2),
-        );
-        if let Some((entity, projection)) = rapier_context.project_point(
-            mouse_pos_2d,
-            true,
-            filter,
-        ) {
-            let (item, transform, item_on_ground) = item_query.get(entity).ok()?;
-            inventory.items.push(item_on_ground.item.clone());
+        // whatever didn't fit stays on the ground
+        if leftover > 0 {
+            item_on_ground.stack.quantity = leftover;
+        } else {
             commands.entity(item).despawn();
-            
-            return Some(());
         }
-        //for (item_entity, item_transform, item_on_ground) in item_query.iter() {
-        //}
 
         Some(())
     })();
 }
 
+fn system_recompute_weights(
+    registry: Res<ItemRegistry>,
+    mut inventories: Query<&mut Inventory>,
+) {
+    if !registry.is_changed() {
+        return;
+    }
+
+    for mut inventory in inventories.iter_mut() {
+        inventory.recompute_weight(&registry);
+    }
+}
+
+fn system_inventory_actions(
+    mut commands: Commands,
+    mut actions: EventReader<InventoryAction>,
+    registry: Res<ItemRegistry>,
+    mut actors: Query<(&Transform, &mut Inventory, Option<&mut Equipment>)>,
+    mut dropped: EventWriter<ItemDropped>,
+    mut used: EventWriter<ItemUsed>,
+    mut equipped: EventWriter<ItemEquipped>,
+) {
+    for action in actions.iter() {
+        let Ok((transform, mut inventory, equipment)) = actors.get_mut(action.actor) else {
+            continue;
+        };
+        let Some(stack) = inventory.get(action.slot).cloned() else {
+            continue;
+        };
+        let position = transform.translation.truncate();
+        let item_type = registry.get(&stack.item_id).map(|definition| definition.item_type.clone());
+
+        match (action.kind, item_type) {
+            (InventoryActionKind::Drop, _) => {
+                let Some(stack) = inventory.take(action.slot, stack.quantity, &registry) else {
+                    continue;
+                };
+                let entity = spawn_item_on_ground(&mut commands, &registry, stack.clone(), position);
+                dropped.send(ItemDropped {
+                    actor: action.actor,
+                    stack,
+                    entity,
+                });
+            }
+            (InventoryActionKind::Use, Some(InventoryItemType::Consumable(stats))) => {
+                inventory.take(action.slot, 1, &registry);
+                used.send(ItemUsed {
+                    actor: action.actor,
+                    item_id: stack.item_id,
+                    stats,
+                });
+            }
+            (InventoryActionKind::Use | InventoryActionKind::Equip, Some(InventoryItemType::Equipment(stats))) => {
+                let Some(mut equipment) = equipment else {
+                    continue;
+                };
+                inventory.take(action.slot, 1, &registry);
+                let replaced = equipment.equip(stats.slot, stack.item_id.clone());
+                if let Some(replaced) = replaced.clone() {
+                    // no room to put it back, leave it at the actor's feet
+                    let leftover = inventory.add(ItemStack::new(replaced.clone(), 1), &registry);
+                    if leftover > 0 {
+                        spawn_item_on_ground(&mut commands, &registry, ItemStack::new(replaced, leftover), position);
+                    }
+                }
+                equipped.send(ItemEquipped {
+                    actor: action.actor,
+                    item_id: stack.item_id,
+                    slot: stats.slot,
+                    replaced,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
 pub struct InventoryPlugin;
 
 impl Plugin for InventoryPlugin {
     fn build(&self, app: &mut App) {
         app
+            .init_resource::<PickupRules>()
+            .add_event::<PickupFailed>()
+            .add_event::<InventoryAction>()
+            .add_event::<ItemDropped>()
+            .add_event::<ItemUsed>()
+            .add_event::<ItemEquipped>()
             .add_systems(OnEnter(GameState::InGame), (
                 apply_deferred,
                 system_setup_inventory,
             ).chain().after(setup_player))
+            .add_systems(FixedUpdate, system_inventory_pickup.after(InteractionSet::Hover))
             .add_systems(Update, (
-                system_inventory_pickup,
-                system_update_inventory,
+                system_inventory_actions,
+                system_recompute_weights,
             ));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{items::test_registry, crafting::{CraftingStation, station_in_reach}};
+
+    fn recipe(inputs: &[(&str, u32)], outputs: &[(&str, u32)]) -> Recipe {
+        let stacks = |stacks: &[(&str, u32)]| stacks.iter()
+            .map(|&(item_id, quantity)| ItemStack::new(item_id, quantity))
+            .collect();
+        Recipe {
+            id: "test".into(),
+            name: "Test".into(),
+            inputs: stacks(inputs),
+            outputs: stacks(outputs),
+            station: None,
+            craft_time: 0.,
+        }
+    }
+
+    fn slots(inventory: &Inventory) -> Vec<Option<ItemStack>> {
+        (0..inventory.inventory_size()).map(|slot| inventory.get(slot).cloned()).collect()
+    }
+
+    #[test]
+    fn add_tops_up_partial_stack() {
+        let registry = test_registry();
+        let mut inventory = Inventory::new(3);
+        assert_eq!(inventory.add(ItemStack::new("berries", 4), &registry), 0);
+        assert_eq!(inventory.add(ItemStack::new("berries", 5), &registry), 0);
+
+        assert_eq!(inventory.get(0), Some(&ItemStack::new("berries", 9)));
+        assert_eq!(inventory.get(1), None);
+        assert_eq!(inventory.total_weight(), 4.5);
+    }
+
+    #[test]
+    fn add_overflows_into_next_empty_slot() {
+        let registry = test_registry();
+        let mut inventory = Inventory::new(3);
+        inventory.add(ItemStack::new("stick", 3), &registry);
+        assert_eq!(inventory.add(ItemStack::new("stick", 6), &registry), 0);
+
+        assert_eq!(inventory.get(0), Some(&ItemStack::new("stick", 5)));
+        assert_eq!(inventory.get(1), Some(&ItemStack::new("stick", 4)));
+        assert_eq!(inventory.count(&"stick".into()), 9);
+    }
+
+    #[test]
+    fn add_returns_leftover_when_full() {
+        let registry = test_registry();
+        let mut inventory = Inventory::new(2);
+        assert_eq!(inventory.add(ItemStack::new("stick", 13), &registry), 3);
+        assert!(inventory.is_full());
+        assert_eq!(inventory.total_weight(), 5.);
+
+        assert_eq!(inventory.add(ItemStack::new("berries", 1), &registry), 1);
+        assert_eq!(inventory.count(&"berries".into()), 0);
+    }
+
+    #[test]
+    fn split_edge_cases() {
+        let registry = test_registry();
+        let mut inventory = Inventory::new(2);
+        inventory.add(ItemStack::new("berries", 6), &registry);
+
+        assert!(!inventory.split(0, 0));
+        assert!(!inventory.split(0, 6));
+        assert!(!inventory.split(1, 1));
+        assert_eq!(inventory.get(0), Some(&ItemStack::new("berries", 6)));
+
+        assert!(inventory.split(0, 2));
+        assert_eq!(inventory.get(0), Some(&ItemStack::new("berries", 4)));
+        assert_eq!(inventory.get(1), Some(&ItemStack::new("berries", 2)));
+
+        // no empty slot left
+        assert!(!inventory.split(0, 1));
+    }
+
+    #[test]
+    fn merge_edge_cases() {
+        let registry = test_registry();
+        let mut inventory = Inventory::new(5);
+        inventory.add(ItemStack::new("stick", 5), &registry);
+        inventory.add(ItemStack::new("berries", 3), &registry);
+        inventory.split(1, 1);
+        inventory.add(ItemStack::new("stick", 2), &registry);
+
+        // mismatched ids, the same slot and an empty slot
+        assert!(!inventory.merge(0, 1, &registry));
+        assert!(!inventory.merge(1, 1, &registry));
+        assert!(!inventory.merge(1, 4, &registry));
+        // the target stack is already full
+        assert!(!inventory.merge(3, 0, &registry));
+        assert_eq!(inventory.get(3), Some(&ItemStack::new("stick", 2)));
+
+        // the whole stack moves and frees its slot
+        assert!(inventory.merge(2, 1, &registry));
+        assert_eq!(inventory.get(1), Some(&ItemStack::new("berries", 3)));
+        assert_eq!(inventory.get(2), None);
+
+        // only what fits moves
+        inventory.take(0, 1, &registry);
+        assert!(inventory.merge(3, 0, &registry));
+        assert_eq!(inventory.get(0), Some(&ItemStack::new("stick", 5)));
+        assert_eq!(inventory.get(3), Some(&ItemStack::new("stick", 1)));
+    }
+
+    #[test]
+    fn craft_missing_inputs_changes_nothing() {
+        let registry = test_registry();
+        let mut inventory = Inventory::new(3);
+        inventory.add(ItemStack::new("stick", 3), &registry);
+        let before = slots(&inventory);
+
+        for recipe in [
+            recipe(&[("stick", 4)], &[("rope", 1)]),
+            // the sticks are there, the stone isn't
+            recipe(&[("stick", 2), ("stone", 1)], &[("rope", 1)]),
+        ] {
+            assert_eq!(inventory.can_craft(&recipe, &registry), Err(CraftError::MissingInputs));
+            assert_eq!(inventory.craft(&recipe, &registry), Err(CraftError::MissingInputs));
+            assert_eq!(slots(&inventory), before);
+            assert_eq!(inventory.total_weight(), 1.5);
+        }
+    }
+
+    #[test]
+    fn craft_no_room_changes_nothing() {
+        let registry = test_registry();
+        let mut inventory = Inventory::new(2);
+        inventory.add(ItemStack::new("stick", 5), &registry);
+        inventory.add(ItemStack::new("stone", 5), &registry);
+        let before = slots(&inventory);
+
+        let recipe = recipe(&[("stick", 1)], &[("rope", 1)]);
+        assert_eq!(inventory.can_craft(&recipe, &registry), Err(CraftError::NoRoom));
+        assert_eq!(inventory.craft(&recipe, &registry), Err(CraftError::NoRoom));
+        assert_eq!(slots(&inventory), before);
+        assert_eq!(inventory.total_weight(), 12.5);
+    }
+
+    #[test]
+    fn craft_outputs_use_freed_slots() {
+        let registry = test_registry();
+        let mut inventory = Inventory::new(2);
+        inventory.add(ItemStack::new("stick", 5), &registry);
+        inventory.add(ItemStack::new("stone", 5), &registry);
+
+        assert_eq!(inventory.craft(&recipe(&[("stick", 5)], &[("rope", 1)]), &registry), Ok(()));
+        assert_eq!(inventory.get(0), Some(&ItemStack::new("rope", 1)));
+        assert_eq!(inventory.get(1), Some(&ItemStack::new("stone", 5)));
+        assert_eq!(inventory.total_weight(), 11.);
+    }
+
+    #[test]
+    fn craft_takes_from_several_stacks() {
+        let registry = test_registry();
+        let mut inventory = Inventory::new(4);
+        inventory.add(ItemStack::new("stick", 8), &registry);
+        inventory.add(ItemStack::new("stone", 2), &registry);
+
+        assert_eq!(inventory.craft(&recipe(&[("stick", 7), ("stone", 2)], &[("rope", 1)]), &registry), Ok(()));
+        assert_eq!(inventory.count(&"stick".into()), 1);
+        assert_eq!(inventory.count(&"stone".into()), 0);
+        assert_eq!(inventory.count(&"rope".into()), 1);
+        assert_eq!(inventory.total_weight(), 1.5);
+    }
+
+    #[test]
+    fn station_reach() {
+        let campfire = (GlobalTransform::from_xyz(100., 0., 0.), CraftingStation {
+            kind: "campfire".into(),
+            radius: 80.,
+        });
+        let stations = [campfire];
+        let near = Vec2::new(30., 0.);
+
+        assert!(station_in_reach(None, near, std::iter::empty()));
+        assert!(!station_in_reach(Some("campfire"), near, std::iter::empty()));
+        assert!(!station_in_reach(Some("workbench"), near, stations.iter().map(|(transform, station)| (transform, station))));
+        assert!(!station_in_reach(Some("campfire"), Vec2::ZERO, stations.iter().map(|(transform, station)| (transform, station))));
+        assert!(station_in_reach(Some("campfire"), near, stations.iter().map(|(transform, station)| (transform, station))));
+    }
+
+    #[test]
+    fn fits_by_weight_caps_pickups() {
+        let registry = test_registry();
+        let rules = EncumbranceRules::default();
+        let mut inventory = Inventory::new(5).with_carry_capacity(10.);
+        let max_weight = rules.max_weight(&inventory);
+        assert_eq!(max_weight, 15.);
+
+        inventory.add(ItemStack::new("stone", 6), &registry);
+        assert_eq!(inventory.total_weight(), 12.);
+        // 3 left, room for one more stone
+        assert_eq!(inventory.fits_by_weight(&ItemStack::new("stone", 5), max_weight, &registry), 1);
+        assert_eq!(inventory.fits_by_weight(&ItemStack::new("berries", 10), max_weight, &registry), 6);
+
+        inventory.add(ItemStack::new("berries", 6), &registry);
+        assert_eq!(inventory.total_weight(), 15.);
+        assert_eq!(inventory.fits_by_weight(&ItemStack::new("stone", 1), max_weight, &registry), 0);
+        // unknown items weigh nothing
+        assert_eq!(inventory.fits_by_weight(&ItemStack::new("feather", 3), max_weight, &registry), 3);
+    }
+}
@@ -0,0 +1,808 @@
+use bevy::{input::mouse::MouseWheel, prelude::*, ui::{FocusPolicy, RelativeCursorPosition}};
+
+use crate::{
+    camera::CameraBindings,
+    container::{Container, OpenContainer, TransferStack},
+    encumbrance::{Encumbrance, EncumbranceRules},
+    game::{GameState, Player},
+    inventory::{Inventory, InventoryAction, InventoryActionKind, ItemStack, PickupFailed},
+    items::ItemRegistry,
+};
+
+const HOTBAR_SIZE: usize = 5;
+const PANEL_COLUMNS: usize = 5;
+const SLOT_SIZE: f32 = 64.;
+const ICON_SIZE: f32 = 40.;
+const PICKUP_MESSAGE_SECONDS: f32 = 2.;
+
+const SLOT_COLOR: Color = Color::rgba(0.65, 0.65, 0.65, 0.8);
+const SLOT_HOVERED_COLOR: Color = Color::rgba(0.75, 0.75, 0.75, 0.9);
+const SLOT_BORDER_COLOR: Color = Color::rgb(0.3, 0.3, 0.3);
+const SLOT_SELECTED_BORDER_COLOR: Color = Color::rgb(1., 0.85, 0.3);
+const WEIGHT_BAR_HEIGHT: f32 = 8.;
+const WEIGHT_BAR_COLOR: Color = Color::rgb(0.4, 0.75, 0.4);
+const WEIGHT_BAR_ENCUMBERED_COLOR: Color = Color::rgb(0.9, 0.6, 0.2);
+const WEIGHT_BAR_BACKGROUND: Color = Color::rgba(0., 0., 0., 0.6);
+
+const NUMBER_KEYS: [KeyCode; 9] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct InventoryBindings {
+    pub toggle_panel: KeyCode,
+    pub use_selected: KeyCode,
+    pub drop_selected: KeyCode,
+    pub close_window: KeyCode,
+}
+
+impl Default for InventoryBindings {
+    fn default() -> Self {
+        Self {
+            toggle_panel: KeyCode::Tab,
+            use_selected: KeyCode::E,
+            drop_selected: KeyCode::Q,
+            close_window: KeyCode::Escape,
+        }
+    }
+}
+
+/// Hotbar slot the local player has selected.
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq, Deref)]
+pub struct SelectedSlot(pub usize);
+
+#[derive(Resource, Default)]
+struct DragState {
+    from: Option<usize>,
+}
+
+#[derive(Component)]
+struct InventoryPanelUi;
+
+#[derive(Component)]
+struct InventorySlotUi(usize);
+
+/// Slot of the container transfer window, showing `slot` of `owner`'s
+/// inventory.
+#[derive(Component)]
+struct TransferSlotUi {
+    owner: Entity,
+    slot: usize,
+}
+
+#[derive(Component)]
+struct TransferWindowUi;
+
+#[derive(Component)]
+struct SlotIconUi;
+
+#[derive(Component)]
+struct SlotCountUi;
+
+#[derive(Component)]
+struct WeightBarFillUi;
+
+#[derive(Component)]
+struct WeightTextUi;
+
+#[derive(Component)]
+struct DragIconUi;
+
+#[derive(Component)]
+struct TooltipUi;
+
+#[derive(Component)]
+struct InventoryMessageUi(Timer);
+
+fn slot_text_style(font_size: f32) -> TextStyle {
+    TextStyle {
+        font_size,
+        color: Color::WHITE,
+        ..default()
+    }
+}
+
+fn spawn_inventory_ui(
+    mut commands: Commands,
+    player_q: Query<&Inventory, (With<Player>, Added<Inventory>)>,
+) {
+    let Ok(inventory) = player_q.get_single() else {
+        return;
+    };
+    let slots = inventory.inventory_size();
+    let hotbar = slots.min(HOTBAR_SIZE);
+
+    commands.spawn((
+        TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(SLOT_SIZE + 28.),
+                width: Val::Percent(100.),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            text: Text::from_section("", slot_text_style(20.))
+                .with_alignment(TextAlignment::Center),
+            ..default()
+        },
+        InventoryMessageUi(Timer::from_seconds(PICKUP_MESSAGE_SECONDS, TimerMode::Once)),
+    ));
+
+    // hotbar, always visible
+    commands.spawn(NodeBundle {
+        style: Style {
+            width: Val::Percent(100.),
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(8.),
+            justify_content: JustifyContent::Center,
+            display: Display::Grid,
+            grid_template_columns: vec![GridTrack::px(SLOT_SIZE); hotbar],
+            column_gap: Val::Px(4.),
+            ..default()
+        },
+        ..default()
+    })
+        .with_children(|builder| {
+            for slot in 0..hotbar {
+                spawn_slot(builder, InventorySlotUi(slot));
+            }
+        });
+
+    // weight bar right above the hotbar, full at the most that can be carried
+    commands.spawn(NodeBundle {
+        style: Style {
+            width: Val::Percent(100.),
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(SLOT_SIZE + 12.),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            column_gap: Val::Px(6.),
+            ..default()
+        },
+        ..default()
+    })
+        .with_children(|builder| {
+            builder.spawn((
+                NodeBundle {
+                    style: Style {
+                        width: Val::Px(hotbar as f32 * (SLOT_SIZE + 4.) - 4.),
+                        height: Val::Px(WEIGHT_BAR_HEIGHT),
+                        ..default()
+                    },
+                    background_color: WEIGHT_BAR_BACKGROUND.into(),
+                    ..default()
+                },
+                RelativeCursorPosition::default(),
+            ))
+                .with_children(|builder| {
+                    builder.spawn((
+                        NodeBundle {
+                            style: Style {
+                                width: Val::Percent(0.),
+                                height: Val::Percent(100.),
+                                ..default()
+                            },
+                            background_color: WEIGHT_BAR_COLOR.into(),
+                            ..default()
+                        },
+                        WeightBarFillUi,
+                    ));
+                });
+            builder.spawn((
+                TextBundle::from_section("", slot_text_style(12.)),
+                WeightTextUi,
+            ));
+        });
+
+    // the rest of the inventory, toggled with a key
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(SLOT_SIZE + 50.),
+                justify_content: JustifyContent::Center,
+                display: Display::Grid,
+                grid_template_columns: vec![GridTrack::px(SLOT_SIZE); PANEL_COLUMNS],
+                column_gap: Val::Px(4.),
+                row_gap: Val::Px(4.),
+                ..default()
+            },
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        InventoryPanelUi,
+    ))
+        .with_children(|builder| {
+            for slot in hotbar..slots {
+                spawn_slot(builder, InventorySlotUi(slot));
+            }
+        });
+
+    commands.spawn((
+        ImageBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Px(ICON_SIZE),
+                height: Val::Px(ICON_SIZE),
+                ..default()
+            },
+            focus_policy: FocusPolicy::Pass,
+            visibility: Visibility::Hidden,
+            z_index: ZIndex::Global(10),
+            ..default()
+        },
+        DragIconUi,
+    ));
+
+    commands.spawn((
+        TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                max_width: Val::Px(240.),
+                padding: UiRect::all(Val::Px(6.)),
+                ..default()
+            },
+            text: Text::from_sections([
+                TextSection::new("", slot_text_style(18.)),
+                TextSection::new("", slot_text_style(14.)),
+            ]),
+            background_color: Color::rgba(0., 0., 0., 0.8).into(),
+            focus_policy: FocusPolicy::Pass,
+            visibility: Visibility::Hidden,
+            z_index: ZIndex::Global(20),
+            ..default()
+        },
+        TooltipUi,
+        RelativeCursorPosition::default(),
+    ));
+}
+
+fn spawn_slot(builder: &mut ChildBuilder, marker: impl Bundle) {
+    builder.spawn((
+        ButtonBundle {
+            style: Style {
+                width: Val::Px(SLOT_SIZE),
+                height: Val::Px(SLOT_SIZE),
+                border: UiRect::all(Val::Px(2.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: SLOT_COLOR.into(),
+            border_color: SLOT_BORDER_COLOR.into(),
+            ..default()
+        },
+        marker,
+    ))
+        .with_children(|builder| {
+            builder.spawn((
+                ImageBundle {
+                    style: Style {
+                        width: Val::Px(ICON_SIZE),
+                        height: Val::Px(ICON_SIZE),
+                        ..default()
+                    },
+                    visibility: Visibility::Hidden,
+                    ..default()
+                },
+                SlotIconUi,
+            ));
+            builder.spawn((
+                TextBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        right: Val::Px(4.),
+                        bottom: Val::Px(2.),
+                        ..default()
+                    },
+                    text: Text::from_section("", slot_text_style(16.)),
+                    ..default()
+                },
+                SlotCountUi,
+            ));
+        });
+}
+
+fn select_slot(
+    keyboard_input: Res<Input<KeyCode>>,
+    camera_bindings: Res<CameraBindings>,
+    mut wheel: EventReader<MouseWheel>,
+    player_q: Query<&Inventory, With<Player>>,
+    mut selected: ResMut<SelectedSlot>,
+) {
+    let Ok(inventory) = player_q.get_single() else {
+        return;
+    };
+    let hotbar = inventory.inventory_size().min(HOTBAR_SIZE);
+    if hotbar == 0 {
+        return;
+    }
+
+    let mut slot = selected.0.min(hotbar - 1);
+    for (index, key) in NUMBER_KEYS.iter().enumerate().take(hotbar) {
+        if keyboard_input.just_pressed(*key) {
+            slot = index;
+        }
+    }
+
+    // the wheel zooms the camera while the modifier is held
+    let wheel_scrolls = !keyboard_input.pressed(camera_bindings.zoom_modifier);
+    for event in wheel.iter().filter(|_| wheel_scrolls) {
+        if event.y > 0. {
+            slot = (slot + hotbar - 1) % hotbar;
+        } else if event.y < 0. {
+            slot = (slot + 1) % hotbar;
+        }
+    }
+
+    selected.set_if_neq(SelectedSlot(slot));
+}
+
+fn selected_slot_actions(
+    keyboard_input: Res<Input<KeyCode>>,
+    bindings: Res<InventoryBindings>,
+    selected: Res<SelectedSlot>,
+    player_q: Query<Entity, (With<Player>, With<Inventory>)>,
+    mut actions: EventWriter<InventoryAction>,
+) {
+    let Ok(player) = player_q.get_single() else {
+        return;
+    };
+
+    let kind = if keyboard_input.just_pressed(bindings.use_selected) {
+        InventoryActionKind::Use
+    } else if keyboard_input.just_pressed(bindings.drop_selected) {
+        InventoryActionKind::Drop
+    } else {
+        return;
+    };
+
+    actions.send(InventoryAction {
+        actor: player,
+        slot: selected.0,
+        kind,
+    });
+}
+
+fn toggle_panel(
+    keyboard_input: Res<Input<KeyCode>>,
+    bindings: Res<InventoryBindings>,
+    mut panel_q: Query<&mut Visibility, With<InventoryPanelUi>>,
+) {
+    if !keyboard_input.just_pressed(bindings.toggle_panel) {
+        return;
+    }
+
+    for mut visibility in panel_q.iter_mut() {
+        *visibility = match *visibility {
+            Visibility::Hidden => Visibility::Visible,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+fn node_under_cursor<'a, T: Component>(
+    cursor: Vec2,
+    nodes: &'a Query<(&T, &Node, &GlobalTransform, &ComputedVisibility)>,
+) -> Option<&'a T> {
+    nodes.iter()
+        .filter(|(_, _, _, visibility)| visibility.is_visible())
+        .find(|(_, node, transform, _)| {
+            Rect::from_center_size(transform.translation().truncate(), node.size()).contains(cursor)
+        })
+        .map(|(marker, _, _, _)| marker)
+}
+
+fn drag_and_drop(
+    mouse: Res<Input<MouseButton>>,
+    windows: Query<&Window>,
+    registry: Res<ItemRegistry>,
+    interactions: Query<(&InventorySlotUi, &Interaction), Changed<Interaction>>,
+    slots: Query<(&InventorySlotUi, &Node, &GlobalTransform, &ComputedVisibility)>,
+    transfer_slots: Query<(&TransferSlotUi, &Node, &GlobalTransform, &ComputedVisibility)>,
+    hovered_ui: Query<&Interaction>,
+    panels: Query<(&RelativeCursorPosition, &ComputedVisibility)>,
+    mut player_q: Query<(Entity, &mut Inventory), With<Player>>,
+    mut drag: ResMut<DragState>,
+    mut drag_icon_q: Query<(&mut Style, &mut UiImage, &mut Visibility), With<DragIconUi>>,
+    mut actions: EventWriter<InventoryAction>,
+    mut transfers: EventWriter<TransferStack>,
+) {
+    let Ok((player, mut inventory)) = player_q.get_single_mut() else {
+        return;
+    };
+    let Ok((mut style, mut image, mut visibility)) = drag_icon_q.get_single_mut() else {
+        return;
+    };
+    let cursor = windows.get_single().ok().and_then(|window| window.cursor_position());
+
+    for (slot, interaction) in interactions.iter() {
+        if *interaction != Interaction::Pressed || drag.from.is_some() {
+            continue;
+        }
+        let Some(stack) = inventory.get(slot.0) else {
+            continue;
+        };
+
+        drag.from = Some(slot.0);
+        let icon = registry.get(&stack.item_id).and_then(|definition| definition.icon_image.clone());
+        image.texture = icon.unwrap_or_default();
+        *visibility = Visibility::Visible;
+    }
+
+    let Some(from) = drag.from else {
+        return;
+    };
+
+    if let Some(cursor) = cursor {
+        style.left = Val::Px(cursor.x - ICON_SIZE / 2.);
+        style.top = Val::Px(cursor.y - ICON_SIZE / 2.);
+    }
+
+    if !mouse.just_released(MouseButton::Left) {
+        return;
+    }
+
+    drag.from = None;
+    *visibility = Visibility::Hidden;
+    let Some(cursor) = cursor else {
+        return;
+    };
+
+    if let Some(to) = node_under_cursor(cursor, &slots) {
+        inventory.move_slot(from, to.0, &registry);
+    } else if let Some(to) = node_under_cursor(cursor, &transfer_slots) {
+        match to.owner == player {
+            true => inventory.move_slot(from, to.slot, &registry),
+            false => transfers.send(TransferStack {
+                from: player,
+                to: to.owner,
+                slot: from,
+            }),
+        }
+    } else {
+        // let go over some other part of the UI, leave the stack where it was
+        let over_ui = hovered_ui.iter().any(|interaction| *interaction != Interaction::None)
+            || panels.iter().any(|(position, visibility)| visibility.is_visible() && position.mouse_over());
+        if over_ui {
+            return;
+        }
+
+        // let go over the world, throw it on the ground
+        actions.send(InventoryAction {
+            actor: player,
+            slot: from,
+            kind: InventoryActionKind::Drop,
+        });
+    }
+}
+
+fn update_slots(
+    player_q: Query<Ref<Inventory>, With<Player>>,
+    registry: Res<ItemRegistry>,
+    selected: Res<SelectedSlot>,
+    mut slots: Query<(&InventorySlotUi, &Interaction, &Children, &mut BackgroundColor, &mut BorderColor)>,
+    mut icons: Query<(&mut UiImage, &mut Visibility), With<SlotIconUi>>,
+    mut counts: Query<&mut Text, With<SlotCountUi>>,
+) {
+    let Ok(inventory) = player_q.get_single() else {
+        return;
+    };
+    let refresh = inventory.is_changed() || registry.is_changed();
+
+    for (slot, interaction, children, mut background, mut border) in slots.iter_mut() {
+        *background = match interaction {
+            Interaction::None => SLOT_COLOR,
+            _ => SLOT_HOVERED_COLOR,
+        }.into();
+        *border = match slot.0 == selected.0 {
+            true => SLOT_SELECTED_BORDER_COLOR,
+            false => SLOT_BORDER_COLOR,
+        }.into();
+
+        if !refresh {
+            continue;
+        }
+
+        show_stack(inventory.get(slot.0), &registry, children, &mut icons, &mut counts);
+    }
+}
+
+/// Puts the icon and count of `stack` into the children of a slot button.
+fn show_stack(
+    stack: Option<&ItemStack>,
+    registry: &ItemRegistry,
+    children: &Children,
+    icons: &mut Query<(&mut UiImage, &mut Visibility), With<SlotIconUi>>,
+    counts: &mut Query<&mut Text, With<SlotCountUi>>,
+) {
+    let icon = stack
+        .and_then(|stack| registry.get(&stack.item_id))
+        .and_then(|definition| definition.icon_image.clone());
+    let count = match stack {
+        Some(stack) if stack.quantity > 1 => stack.quantity.to_string(),
+        // no icon to show, fall back to the name
+        Some(stack) if icon.is_none() => registry.name(&stack.item_id).into(),
+        _ => String::new(),
+    };
+
+    for child in children.iter() {
+        if let Ok((mut image, mut visibility)) = icons.get_mut(*child) {
+            *visibility = match icon {
+                Some(_) => Visibility::Inherited,
+                None => Visibility::Hidden,
+            };
+            image.texture = icon.clone().unwrap_or_default();
+        }
+        if let Ok(mut text) = counts.get_mut(*child) {
+            text.sections[0].value = count.clone();
+        }
+    }
+}
+
+fn update_weight_bar(
+    rules: Res<EncumbranceRules>,
+    player_q: Query<(Ref<Inventory>, Ref<Encumbrance>), With<Player>>,
+    mut fill_q: Query<(&mut Style, &mut BackgroundColor), With<WeightBarFillUi>>,
+    mut text_q: Query<&mut Text, With<WeightTextUi>>,
+) {
+    let Ok((inventory, encumbrance)) = player_q.get_single() else {
+        return;
+    };
+    if !(inventory.is_changed() || encumbrance.is_changed() || rules.is_changed()) {
+        return;
+    }
+
+    let max_weight = rules.max_weight(&inventory);
+    let fill = (inventory.total_weight() / max_weight).clamp(0., 1.);
+    for (mut style, mut background) in fill_q.iter_mut() {
+        style.width = Val::Percent(fill * 100.);
+        *background = match encumbrance.tier {
+            None => WEIGHT_BAR_COLOR,
+            Some(_) => WEIGHT_BAR_ENCUMBERED_COLOR,
+        }.into();
+    }
+
+    let tier = encumbrance.tier.map(|tier| format!(" {}", rules.tiers[tier].name));
+    for mut text in text_q.iter_mut() {
+        text.sections[0].value = format!(
+            "{:.1} / {:.1}{}",
+            inventory.total_weight(),
+            inventory.carry_capacity,
+            tier.as_deref().unwrap_or_default(),
+        );
+    }
+}
+
+fn update_tooltip(
+    windows: Query<&Window>,
+    drag: Res<DragState>,
+    registry: Res<ItemRegistry>,
+    player_q: Query<&Inventory, With<Player>>,
+    slots: Query<(&InventorySlotUi, &Interaction)>,
+    mut tooltip_q: Query<(&mut Text, &mut Style, &mut Visibility), With<TooltipUi>>,
+) {
+    let Ok((mut text, mut style, mut visibility)) = tooltip_q.get_single_mut() else {
+        return;
+    };
+
+    let definition = (|| {
+        if drag.from.is_some() {
+            return None;
+        }
+        let inventory = player_q.get_single().ok()?;
+        let (slot, _) = slots.iter().find(|(_, interaction)| **interaction == Interaction::Hovered)?;
+        registry.get(&inventory.get(slot.0)?.item_id)
+    })();
+    let cursor = windows.get_single().ok().and_then(|window| window.cursor_position());
+
+    let (Some(definition), Some(cursor)) = (definition, cursor) else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+
+    if text.sections[0].value != definition.name {
+        text.sections[0].value = definition.name.clone();
+        text.sections[1].value = match definition.description.is_empty() {
+            true => String::new(),
+            false => format!("\n{}", definition.description),
+        };
+        if definition.weight > 0. {
+            text.sections[1].value += &format!("\nWeight {:.1}", definition.weight);
+        }
+    }
+    style.left = Val::Px(cursor.x + 16.);
+    style.bottom = Val::Auto;
+    style.top = Val::Px(cursor.y - 60.);
+    *visibility = Visibility::Visible;
+}
+
+fn spawn_transfer_window(
+    mut commands: Commands,
+    open: Res<OpenContainer>,
+    windows_q: Query<Entity, With<TransferWindowUi>>,
+    player_q: Query<(Entity, &Inventory), With<Player>>,
+    containers: Query<(&Container, &Inventory), Without<Player>>,
+) {
+    if !open.is_changed() {
+        return;
+    }
+    for window in windows_q.iter() {
+        commands.entity(window).despawn_recursive();
+    }
+
+    let Some(container) = open.0 else {
+        return;
+    };
+    let Ok((player, player_inventory)) = player_q.get_single() else {
+        return;
+    };
+    let Ok((container_info, container_inventory)) = containers.get(container) else {
+        return;
+    };
+
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                position_type: PositionType::Absolute,
+                top: Val::Px(40.),
+                justify_content: JustifyContent::Center,
+                column_gap: Val::Px(24.),
+                ..default()
+            },
+            ..default()
+        },
+        TransferWindowUi,
+    ))
+        .with_children(|builder| {
+            spawn_transfer_pane(builder, "Inventory", player, player_inventory.inventory_size());
+            spawn_transfer_pane(builder, &container_info.name, container, container_inventory.inventory_size());
+        });
+}
+
+fn spawn_transfer_pane(builder: &mut ChildBuilder, title: &str, owner: Entity, slots: usize) {
+    builder.spawn((
+        NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(8.)),
+                row_gap: Val::Px(6.),
+                ..default()
+            },
+            background_color: Color::rgba(0., 0., 0., 0.6).into(),
+            ..default()
+        },
+        RelativeCursorPosition::default(),
+    ))
+        .with_children(|builder| {
+            builder.spawn(TextBundle::from_section(title, slot_text_style(18.)));
+            builder.spawn(NodeBundle {
+                style: Style {
+                    display: Display::Grid,
+                    grid_template_columns: vec![GridTrack::px(SLOT_SIZE); PANEL_COLUMNS],
+                    column_gap: Val::Px(4.),
+                    row_gap: Val::Px(4.),
+                    ..default()
+                },
+                ..default()
+            })
+                .with_children(|builder| {
+                    for slot in 0..slots {
+                        spawn_slot(builder, TransferSlotUi { owner, slot });
+                    }
+                });
+        });
+}
+
+/// Clicking a stack in either pane sends it to the other one.
+fn transfer_clicked_slot(
+    open: Res<OpenContainer>,
+    player_q: Query<Entity, With<Player>>,
+    interactions: Query<(&TransferSlotUi, &Interaction), Changed<Interaction>>,
+    mut transfers: EventWriter<TransferStack>,
+) {
+    let (Some(container), Ok(player)) = (open.0, player_q.get_single()) else {
+        return;
+    };
+
+    for (slot, interaction) in interactions.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        let to = if slot.owner == player { container } else { player };
+        transfers.send(TransferStack {
+            from: slot.owner,
+            to,
+            slot: slot.slot,
+        });
+    }
+}
+
+fn update_transfer_slots(
+    registry: Res<ItemRegistry>,
+    inventories: Query<Ref<Inventory>>,
+    mut slots: Query<(Ref<TransferSlotUi>, &Interaction, &Children, &mut BackgroundColor)>,
+    mut icons: Query<(&mut UiImage, &mut Visibility), With<SlotIconUi>>,
+    mut counts: Query<&mut Text, With<SlotCountUi>>,
+) {
+    for (slot, interaction, children, mut background) in slots.iter_mut() {
+        *background = match interaction {
+            Interaction::None => SLOT_COLOR,
+            _ => SLOT_HOVERED_COLOR,
+        }.into();
+
+        let Ok(inventory) = inventories.get(slot.owner) else {
+            continue;
+        };
+        if slot.is_added() || inventory.is_changed() || registry.is_changed() {
+            show_stack(inventory.get(slot.slot), &registry, children, &mut icons, &mut counts);
+        }
+    }
+}
+
+fn close_transfer_window(
+    keyboard_input: Res<Input<KeyCode>>,
+    bindings: Res<InventoryBindings>,
+    mut open: ResMut<OpenContainer>,
+) {
+    if open.0.is_some() && keyboard_input.just_pressed(bindings.close_window) {
+        open.0 = None;
+    }
+}
+
+fn pickup_failed_message(
+    time: Res<Time>,
+    mut events: EventReader<PickupFailed>,
+    mut message_q: Query<(&mut Text, &mut InventoryMessageUi)>,
+) {
+    let Ok((mut text, mut message)) = message_q.get_single_mut() else {
+        return;
+    };
+
+    if let Some(event) = events.iter().last() {
+        text.sections[0].value = event.reason.message().into();
+        message.0.reset();
+    }
+
+    if message.0.tick(time.delta()).just_finished() {
+        text.sections[0].value.clear();
+    }
+}
+
+#[derive(Default)]
+pub struct InventoryUiPlugin;
+
+impl Plugin for InventoryUiPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<InventoryBindings>()
+            .init_resource::<SelectedSlot>()
+            .init_resource::<DragState>()
+            .add_systems(Update, (
+                spawn_inventory_ui,
+                toggle_panel,
+                select_slot,
+                selected_slot_actions.after(select_slot),
+                drag_and_drop,
+                update_slots.after(drag_and_drop).after(select_slot),
+                update_tooltip,
+                update_weight_bar,
+                pickup_failed_message,
+                close_transfer_window,
+                spawn_transfer_window.after(close_transfer_window),
+                transfer_clicked_slot,
+                update_transfer_slots.after(spawn_transfer_window),
+            ).run_if(in_state(GameState::InGame)));
+    }
+}
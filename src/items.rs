@@ -0,0 +1,210 @@
+use std::path::PathBuf;
+
+use bevy::{
+    asset::{AssetLoader, AssetPath, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
+
+/// Stable identifier of an item definition, used by save data, levels and
+/// inventories instead of the display name.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ItemId(pub String);
+
+impl From<&str> for ItemId {
+    fn from(id: &str) -> Self {
+        ItemId(id.into())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum EquipmentSlot {
+    Head,
+    Body,
+    Hands,
+    LightSource,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ConsumableStats {
+    pub health: f32,
+}
+
+/// Added on top of the wearer's base stats while equipped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct StatModifiers {
+    /// Fraction of base speed, `-0.1` is ten percent slower.
+    pub speed: f32,
+    pub fov_range: f32,
+    pub fov_angle_degrees: f32,
+    pub max_health: f32,
+    pub light_radius: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct EquipmentStats {
+    pub slot: EquipmentSlot,
+    #[serde(default)]
+    pub modifiers: StatModifiers,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub enum InventoryItemType {
+    Consumable(ConsumableStats),
+    Equipment(EquipmentStats),
+    Material,
+}
+
+fn default_max_stack() -> u32 {
+    1
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ItemDefinition {
+    pub id: ItemId,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Path of the icon image, relative to the assets folder.
+    #[serde(default)]
+    pub icon: Option<String>,
+    #[serde(skip)]
+    pub icon_image: Option<Handle<Image>>,
+    pub item_type: InventoryItemType,
+    #[serde(default = "default_max_stack")]
+    pub max_stack: u32,
+    #[serde(default)]
+    pub weight: f32,
+}
+
+/// Item definitions as read from a `.items.ron` file.
+#[derive(Debug, Deserialize, TypeUuid, TypePath)]
+#[uuid = "6a1c30f4-3bd1-4be4-9d0b-5f1d22e9a7c3"]
+pub struct ItemDatabase {
+    pub items: Vec<ItemDefinition>,
+}
+
+#[derive(Default)]
+pub struct ItemDatabaseLoader;
+
+impl AssetLoader for ItemDatabaseLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let mut database: ItemDatabase = ron::de::from_bytes(bytes)?;
+
+            let mut dependencies = vec![];
+            for item in database.items.iter_mut() {
+                if let Some(icon) = &item.icon {
+                    let path = AssetPath::new(PathBuf::from(icon), None);
+                    item.icon_image = Some(load_context.get_handle(path.clone()));
+                    dependencies.push(path);
+                }
+            }
+
+            load_context.set_default_asset(LoadedAsset::new(database).with_dependencies(dependencies));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["items.ron"]
+    }
+}
+
+/// Every item that exists in the game, by id. Filled from the
+/// [`ItemDatabase`] asset whenever it is loaded or changes on disk.
+#[derive(Resource, Default)]
+pub struct ItemRegistry {
+    items: HashMap<ItemId, ItemDefinition>,
+}
+
+impl ItemRegistry {
+    pub fn insert(&mut self, definition: ItemDefinition) {
+        self.items.insert(definition.id.clone(), definition);
+    }
+
+    pub fn get(&self, id: &ItemId) -> Option<&ItemDefinition> {
+        self.items.get(id)
+    }
+
+    /// Unknown items don't stack.
+    pub fn max_stack(&self, id: &ItemId) -> u32 {
+        self.get(id).map_or(1, |definition| definition.max_stack.max(1))
+    }
+
+    /// Unknown items weigh nothing.
+    pub fn weight(&self, id: &ItemId) -> f32 {
+        self.get(id).map_or(0., |definition| definition.weight)
+    }
+
+    pub fn name<'a>(&'a self, id: &'a ItemId) -> &'a str {
+        self.get(id).map_or(&id.0[..], |definition| &definition.name[..])
+    }
+}
+
+/// Materials for tests:
+/// - `berries`: stacks of 10, weighs 0.5.
+/// - `stick`: stacks of 5, weighs 0.5.
+/// - `stone`: stacks of 5, weighs 2.
+/// - `rope`: doesn't stack, weighs 1.
+#[cfg(test)]
+pub(crate) fn test_registry() -> ItemRegistry {
+    let mut registry = ItemRegistry::default();
+    for (id, max_stack, weight) in [("berries", 10, 0.5), ("stick", 5, 0.5), ("stone", 5, 2.), ("rope", 1, 1.)] {
+        registry.insert(ItemDefinition {
+            id: id.into(),
+            name: id.to_string(),
+            description: String::new(),
+            icon: None,
+            icon_image: None,
+            item_type: InventoryItemType::Material,
+            max_stack,
+            weight,
+        });
+    }
+    registry
+}
+
+fn sync_item_registry(
+    mut events: EventReader<AssetEvent<ItemDatabase>>,
+    databases: Res<Assets<ItemDatabase>>,
+    mut registry: ResMut<ItemRegistry>,
+) {
+    for event in events.iter() {
+        let (AssetEvent::Created { handle } | AssetEvent::Modified { handle }) = event else {
+            continue;
+        };
+        let Some(database) = databases.get(handle) else {
+            continue;
+        };
+
+        registry.items.clear();
+        for definition in database.items.iter() {
+            registry.insert(definition.clone());
+        }
+    }
+}
+
+/// Registers the item database asset. Must be added before the
+/// `LoadingPlugin` so the database can be part of `GameAssets`.
+#[derive(Default)]
+pub struct ItemsPlugin;
+
+impl Plugin for ItemsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_asset::<ItemDatabase>()
+            .init_asset_loader::<ItemDatabaseLoader>()
+            .init_resource::<ItemRegistry>()
+            .add_systems(Update, sync_item_registry);
+    }
+}
@@ -0,0 +1,320 @@
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+    utils::HashMap,
+};
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+
+use crate::{items::ItemId, tiled::TiledMapLoader, prefab::{PrefabDef, Prefabs, PrefabsLoader}, field_of_view::OCCLUDER_GROUP, auto_collider::TraceSettings, sorting::SortingLayer};
+
+/// Spawn point the player starts at.
+pub const PLAYER_SPAWN: &str = "player";
+
+fn one() -> f32 {
+    1.
+}
+
+fn one_u32() -> u32 {
+    1
+}
+
+fn prop_z() -> f32 {
+    1.
+}
+
+/// What a collider blocks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+pub enum ColliderLayer {
+    /// Blocks movement, and sight when its prop is an occluder.
+    #[default]
+    Solid,
+    /// Can be walked over and pointed at, but blocks nothing.
+    Walkable,
+}
+
+impl ColliderLayer {
+    pub fn groups(self, occluder: bool) -> CollisionGroups {
+        match self {
+            ColliderLayer::Solid if occluder => CollisionGroups::new(Group::GROUP_1 | OCCLUDER_GROUP, Group::ALL),
+            ColliderLayer::Solid => CollisionGroups::new(Group::GROUP_1, Group::ALL),
+            ColliderLayer::Walkable => CollisionGroups::new(Group::GROUP_2, Group::ALL),
+        }
+    }
+
+    pub fn is_sensor(self) -> bool {
+        self == ColliderLayer::Walkable
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum ShapeDef {
+    Cuboid { half_extents: Vec2 },
+    Ball { radius: f32 },
+    /// Closed outline, may be concave.
+    Polygon { points: Vec<Vec2> },
+    /// Open chain of segments.
+    Polyline { points: Vec<Vec2> },
+    /// Traced from the alpha of the prop's texture once it has loaded.
+    Traced(TraceSettings),
+}
+
+impl ShapeDef {
+    /// `None` for shapes that can't be built until their image has loaded.
+    pub fn collider(&self) -> Option<Collider> {
+        let collider = match self {
+            ShapeDef::Cuboid { half_extents } => Collider::cuboid(half_extents.x, half_extents.y),
+            ShapeDef::Ball { radius } => Collider::ball(*radius),
+            ShapeDef::Polygon { points } => {
+                let count = points.len() as u32;
+                let indices: Vec<[u32; 2]> = (0..count).map(|i| [i, (i + 1) % count]).collect();
+                Collider::convex_decomposition(points, &indices)
+            }
+            ShapeDef::Polyline { points } => Collider::polyline(points.clone(), None),
+            ShapeDef::Traced(_) => return None,
+        };
+        Some(collider)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ColliderDef {
+    pub shape: ShapeDef,
+    /// Relative to the prop.
+    #[serde(default)]
+    pub offset: Vec2,
+    /// Radians, relative to the prop.
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default)]
+    pub layer: ColliderLayer,
+}
+
+impl ColliderDef {
+    /// Whether the collider can sit on the prop itself instead of a child.
+    pub fn is_centered(&self) -> bool {
+        self.offset == Vec2::ZERO && self.rotation == 0.
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum Visual {
+    /// Image path, relative to the assets folder.
+    Texture(String),
+    Rect { color: Color, size: Vec2 },
+    Circle { color: Color, radius: f32 },
+    /// Tile `index` of one of the level's tilesets, at the tile size unless
+    /// `size` is given.
+    Tile { tileset: usize, index: usize, size: Option<Vec2> },
+}
+
+/// Image cut into a grid of equally sized tiles.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TilesetDef {
+    pub image: String,
+    pub tile_size: Vec2,
+    pub columns: usize,
+    pub rows: usize,
+    /// Gap between tiles.
+    #[serde(default)]
+    pub spacing: f32,
+    /// Gap around the edge of the image.
+    #[serde(default)]
+    pub margin: f32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TileDef {
+    pub tileset: usize,
+    pub index: usize,
+    /// Center of the tile.
+    pub position: Vec2,
+    #[serde(default)]
+    pub flip_x: bool,
+    #[serde(default)]
+    pub flip_y: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TileLayerDef {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub z: f32,
+    pub tiles: Vec<TileDef>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ContainerDef {
+    pub name: String,
+    pub slots: usize,
+    /// Loot table the container is filled from when the level starts.
+    pub loot: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct StationDef {
+    pub kind: String,
+    pub radius: f32,
+}
+
+/// A prop placed in the level. Starts from `prefab` when given, any other
+/// field that is set replaces the prefab's.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PropDef {
+    pub prefab: Option<String>,
+    pub name: Option<String>,
+    pub position: Vec2,
+    #[serde(default = "prop_z")]
+    pub z: f32,
+    /// Radians.
+    #[serde(default)]
+    pub rotation: f32,
+    pub visual: Option<Visual>,
+    pub flip_x: Option<bool>,
+    pub flip_y: Option<bool>,
+    pub layer: Option<SortingLayer>,
+    pub pivot: Option<f32>,
+    pub colliders: Option<Vec<ColliderDef>>,
+    pub occluder: Option<bool>,
+    pub container: Option<ContainerDef>,
+    pub station: Option<StationDef>,
+    pub interactable: Option<String>,
+}
+
+impl PropDef {
+    /// The prefab with this prop's overrides applied.
+    pub fn resolve(&self, prefabs: Option<&Prefabs>) -> PrefabDef {
+        let mut prefab = match &self.prefab {
+            Some(name) => match prefabs.and_then(|prefabs| prefabs.get(name)) {
+                Some(prefab) => prefab.clone(),
+                None => {
+                    warn!("unknown prefab {name}");
+                    PrefabDef::default()
+                }
+            },
+            None => PrefabDef::default(),
+        };
+
+        if self.name.is_some() {
+            prefab.name = self.name.clone();
+        }
+        if self.visual.is_some() {
+            prefab.visual = self.visual.clone();
+        }
+        if let Some(flip_x) = self.flip_x {
+            prefab.flip_x = flip_x;
+        }
+        if let Some(flip_y) = self.flip_y {
+            prefab.flip_y = flip_y;
+        }
+        if self.layer.is_some() {
+            prefab.layer = self.layer;
+        }
+        if self.pivot.is_some() {
+            prefab.pivot = self.pivot;
+        }
+        if let Some(colliders) = &self.colliders {
+            prefab.colliders = colliders.clone();
+        }
+        if let Some(occluder) = self.occluder {
+            prefab.occluder = occluder;
+        }
+        if self.container.is_some() {
+            prefab.container = self.container.clone();
+        }
+        if self.station.is_some() {
+            prefab.station = self.station.clone();
+        }
+        if self.interactable.is_some() {
+            prefab.interactable = self.interactable.clone();
+        }
+        prefab
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BackgroundDef {
+    pub texture: String,
+    #[serde(default)]
+    pub position: Vec2,
+    #[serde(default = "one")]
+    pub scale: f32,
+    #[serde(default)]
+    pub z: f32,
+    /// Whether `position` is the top left corner instead of the center.
+    #[serde(default)]
+    pub corner: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ItemSpawn {
+    pub item: ItemId,
+    #[serde(default = "one_u32")]
+    pub quantity: u32,
+    pub position: Vec2,
+}
+
+/// Layout of a level as read from a `.level.ron` file or a Tiled map.
+#[derive(Clone, Debug, Deserialize, TypeUuid, TypePath)]
+#[uuid = "5d2c8e71-3a94-4f06-b1e8-92c47d6a0f13"]
+pub struct Level {
+    /// Where cameras may look. Taken from the backgrounds when not set.
+    pub bounds: Option<Rect>,
+    #[serde(default)]
+    pub backgrounds: Vec<BackgroundDef>,
+    #[serde(default)]
+    pub tilesets: Vec<TilesetDef>,
+    #[serde(default)]
+    pub tile_layers: Vec<TileLayerDef>,
+    #[serde(default)]
+    pub props: Vec<PropDef>,
+    #[serde(default)]
+    pub items: Vec<ItemSpawn>,
+    #[serde(default)]
+    pub spawn_points: HashMap<String, Vec2>,
+}
+
+impl Level {
+    pub fn spawn_point(&self, name: &str) -> Option<Vec2> {
+        self.spawn_points.get(name).copied()
+    }
+}
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let level: Level = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(level));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+/// Registers the level and prefab assets. Like the `ItemsPlugin`, it must be
+/// added before the `LoadingPlugin`.
+#[derive(Default)]
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_asset::<Level>()
+            .init_asset_loader::<LevelLoader>()
+            .init_asset_loader::<TiledMapLoader>()
+            .add_asset::<Prefabs>()
+            .init_asset_loader::<PrefabsLoader>();
+    }
+}
@@ -2,6 +2,8 @@ use bevy::prelude::*;
 use bevy_asset_loader::prelude::*;
 use iyes_progress::ProgressPlugin;
 
+use crate::{items::ItemDatabase, loot::LootTables, crafting::RecipeBook, level::Level, prefab::Prefabs};
+
 #[derive(AssetCollection, Resource)]
 pub struct GameAssets {
     #[asset(path = "character/character-sheet.png")]
@@ -12,6 +14,17 @@ pub struct GameAssets {
     pub tent_texture: Handle<Image>,
     #[asset(path = "textures/stationary/sitting-log.png")]
     pub sitting_log_texture: Handle<Image>,
+    #[asset(path = "data/items.items.ron")]
+    pub items: Handle<ItemDatabase>,
+    #[asset(path = "data/world.loot.ron")]
+    pub loot_tables: Handle<LootTables>,
+    #[asset(path = "data/base.recipes.ron")]
+    pub recipes: Handle<RecipeBook>,
+    #[asset(path = "data/props.prefabs.ron")]
+    pub prefabs: Handle<Prefabs>,
+    /// A `.level.ron` file or a Tiled `.tmj` map.
+    #[asset(path = "data/campsite.level.ron")]
+    pub level: Handle<Level>,
 }
 
 #[derive(Default)]
@@ -0,0 +1,193 @@
+use std::env;
+
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+    utils::HashMap,
+};
+use rand::{distributions::{Distribution, WeightedIndex}, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+
+use crate::{inventory::{Inventory, ItemStack}, items::{ItemId, ItemRegistry}};
+
+/// Set to a number to change the loot rolled this session.
+const LOOT_SEED_ENV: &str = "GOO_LOOT_SEED";
+const DEFAULT_LOOT_SEED: u64 = 0x600d_1007;
+
+fn one() -> u32 {
+    1
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct LootEntry {
+    pub item: ItemId,
+    /// Chance of this entry relative to the others in the table.
+    pub weight: u32,
+    #[serde(default = "one")]
+    pub min: u32,
+    #[serde(default = "one")]
+    pub max: u32,
+}
+
+/// Rolls between `min_rolls` and `max_rolls` times, each roll picking one
+/// entry by weight.
+#[derive(Clone, Debug, Deserialize)]
+pub struct LootTable {
+    #[serde(default = "one")]
+    pub min_rolls: u32,
+    #[serde(default = "one")]
+    pub max_rolls: u32,
+    pub entries: Vec<LootEntry>,
+}
+
+impl LootTable {
+    /// The same rng state always gives the same stacks.
+    pub fn roll(&self, rng: &mut impl Rng) -> Vec<ItemStack> {
+        let Ok(index) = WeightedIndex::new(self.entries.iter().map(|entry| entry.weight)) else {
+            return vec![];
+        };
+
+        let rolls = rng.gen_range(self.min_rolls..=self.max_rolls.max(self.min_rolls));
+        (0..rolls)
+            .map(|_| {
+                let entry = &self.entries[index.sample(rng)];
+                let quantity = rng.gen_range(entry.min..=entry.max.max(entry.min));
+                ItemStack::new(entry.item.clone(), quantity)
+            })
+            .filter(|stack| stack.quantity > 0)
+            .collect()
+    }
+
+    /// Rolls into `inventory`. Whatever doesn't fit is lost.
+    pub fn fill(&self, inventory: &mut Inventory, registry: &ItemRegistry, rng: &mut impl Rng) {
+        for stack in self.roll(rng) {
+            inventory.add(stack, registry);
+        }
+    }
+}
+
+/// Named loot tables as read from a `.loot.ron` file.
+#[derive(Debug, Deserialize, TypeUuid, TypePath)]
+#[uuid = "0b7f4a52-9e35-4c1f-a7d2-3c8e61f05b94"]
+pub struct LootTables {
+    pub tables: HashMap<String, LootTable>,
+}
+
+impl LootTables {
+    pub fn get(&self, name: &str) -> Option<&LootTable> {
+        self.tables.get(name)
+    }
+}
+
+#[derive(Default)]
+pub struct LootTablesLoader;
+
+impl AssetLoader for LootTablesLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let tables: LootTables = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(tables));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["loot.ron"]
+    }
+}
+
+/// Randomness for everything rolled from loot tables. Seeded so a session can
+/// be reproduced.
+#[derive(Resource)]
+pub struct LootRng(pub ChaCha8Rng);
+
+impl LootRng {
+    pub fn from_seed(seed: u64) -> Self {
+        LootRng(ChaCha8Rng::seed_from_u64(seed))
+    }
+}
+
+impl Default for LootRng {
+    fn default() -> Self {
+        let seed = env::var(LOOT_SEED_ENV)
+            .ok()
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or(DEFAULT_LOOT_SEED);
+        LootRng::from_seed(seed)
+    }
+}
+
+/// Registers the loot table asset. Like the `ItemsPlugin`, it must be added
+/// before the `LoadingPlugin`.
+#[derive(Default)]
+pub struct LootPlugin;
+
+impl Plugin for LootPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_asset::<LootTables>()
+            .init_asset_loader::<LootTablesLoader>()
+            .init_resource::<LootRng>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::test_registry;
+
+    fn entry(item: &str, weight: u32, min: u32, max: u32) -> LootEntry {
+        LootEntry {
+            item: item.into(),
+            weight,
+            min,
+            max,
+        }
+    }
+
+    fn table(min_rolls: u32, max_rolls: u32, entries: Vec<LootEntry>) -> LootTable {
+        LootTable {
+            min_rolls,
+            max_rolls,
+            entries,
+        }
+    }
+
+    #[test]
+    fn same_seed_same_stacks() {
+        let table = table(2, 6, vec![
+            entry("berries", 3, 1, 4),
+            entry("stick", 1, 2, 2),
+            entry("stone", 5, 1, 1),
+        ]);
+        let first = table.roll(&mut LootRng::from_seed(42).0);
+        let second = table.roll(&mut LootRng::from_seed(42).0);
+
+        assert!(!first.is_empty());
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn empty_tables_give_nothing() {
+        let mut rng = LootRng::from_seed(1).0;
+        assert!(table(1, 3, vec![]).roll(&mut rng).is_empty());
+        assert!(table(1, 3, vec![entry("berries", 0, 1, 1), entry("stick", 0, 1, 1)]).roll(&mut rng).is_empty());
+    }
+
+    #[test]
+    fn fill_stops_at_capacity() {
+        let registry = test_registry();
+        let mut inventory = Inventory::new(2);
+
+        table(10, 10, vec![entry("stick", 1, 5, 5)]).fill(&mut inventory, &registry, &mut LootRng::from_seed(3).0);
+
+        assert!(inventory.is_full());
+        assert_eq!(inventory.count(&"stick".into()), 10);
+    }
+}
@@ -3,16 +3,35 @@ mod game;
 mod mouse;
 mod input;
 mod camera;
+mod camera_shake;
 mod animation;
 mod animator;
 mod field_of_view;
 mod scene;
+mod level;
+mod tiled;
+mod prefab;
+mod auto_collider;
+mod sorting;
 mod inventory;
+mod inventory_ui;
+mod replay;
+mod facing;
+mod interaction;
+mod items;
+mod equipment;
+mod stats;
+mod loot;
+mod container;
+mod crafting;
+mod crafting_ui;
+mod encumbrance;
 
 use bevy::{prelude::*, diagnostic::{LogDiagnosticsPlugin, FrameTimeDiagnosticsPlugin}, window::{PresentMode, WindowTheme}};
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_rapier2d::{prelude::{RapierPhysicsPlugin, NoUserData}, render::RapierDebugRenderPlugin};
 use game::GamePlugin;
+use input::FixedPhysicsPlugin;
 
 fn main() {
     App::new()
@@ -26,7 +45,8 @@ fn main() {
                 }),
                 ..default()
             }),
-            RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0),
+            RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0).with_default_system_setup(false),
+            FixedPhysicsPlugin,
             RapierDebugRenderPlugin::default(),
             GamePlugin,
         ))
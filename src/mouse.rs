@@ -1,49 +1,144 @@
-use std::{fmt::Debug, hash::Hash};
+use std::f32::consts::{PI, TAU};
 
 use bevy::{
-    math::{Quat, Vec2},
+    math::Vec2,
     prelude::*,
 };
 
-use crate::game::{Player, GameState, MainCamera};
+use crate::{game::{Player, MainCamera}, input::{ActionInput, InputSet}, facing::Facing, camera_shake::CameraShake};
 
-fn mouse_look(
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AimSmoothing {
+    /// Turn straight at the target, limited only by the max turn rate.
+    Linear,
+    /// Close half of the remaining angle every `half_life` seconds.
+    Exponential { half_life: f32 },
+}
+
+/// How fast aiming turns towards the cursor. Insert it on an entity to
+/// override the global resource for that entity.
+#[derive(Resource, Component, Clone, Copy, Debug)]
+pub struct AimSettings {
+    /// Degrees per second.
+    pub max_turn_rate: f32,
+    pub smoothing: AimSmoothing,
+    /// Snap to the target every tick, ignoring the other settings.
+    pub instant: bool,
+}
+
+impl Default for AimSettings {
+    fn default() -> Self {
+        Self {
+            max_turn_rate: 720.,
+            smoothing: AimSmoothing::Exponential { half_life: 0.11 },
+            instant: false,
+        }
+    }
+}
+
+impl AimSettings {
+    /// Angle to face after turning from `current` towards `target` for
+    /// `delta` seconds, always going the short way round.
+    pub fn turn(&self, current: f32, target: f32, delta: f32) -> f32 {
+        if self.instant {
+            return wrap_angle(target);
+        }
+
+        let difference = wrap_angle(target - current);
+        let step = match self.smoothing {
+            AimSmoothing::Linear => difference,
+            AimSmoothing::Exponential { half_life } if half_life > 0. => {
+                difference * (1. - 0.5_f32.powf(delta / half_life))
+            }
+            AimSmoothing::Exponential { .. } => difference,
+        };
+        let max_step = self.max_turn_rate.to_radians() * delta;
+
+        wrap_angle(current + step.clamp(-max_step, max_step))
+    }
+}
+
+/// Maps an angle into `[-PI, PI)`.
+pub fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(TAU) - PI
+}
+
+/// Cursor position in world space, updated once per frame for everything
+/// that needs to know what the mouse points at. Camera shake is taken back
+/// out, so aim doesn't jitter with the view.
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq, Deref)]
+pub struct CursorWorldPosition(pub Option<Vec2>);
+
+fn update_cursor_world_position(
     windows: Query<&Window>,
-    cam_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
-    mut transform_query: Query<&mut Transform, With<Player>>,
-    time: Res<Time>,
+    cam_query: Query<(&Camera, &GlobalTransform, Option<&CameraShake>), With<MainCamera>>,
+    mut cursor_world_position: ResMut<CursorWorldPosition>,
 ) {
-    (|| {
-        let wnd = windows.single();
+    let position = (|| {
+        let wnd = windows.get_single().ok()?;
+        let (camera, camera_transform, shake) = cam_query.get_single().ok()?;
 
-        let (camera, camera_transform) = cam_query.get_single().ok()?;
+        // the shake only moves on in Update, so it's still the one the
+        // camera was last drawn with
+        let position = camera.viewport_to_world_2d(camera_transform, wnd.cursor_position()?)?;
+        Some(shake.map_or(position, |shake| shake.unshake(camera_transform.translation().truncate(), position)))
+    })();
 
-        let mut transform = transform_query.get_single_mut().ok()?;
+    cursor_world_position.set_if_neq(CursorWorldPosition(position));
+}
+
+fn mouse_look(
+    action_input: Res<ActionInput>,
+    aim_settings: Res<AimSettings>,
+    mut facing_query: Query<(&Transform, &mut Facing, Option<&AimSettings>), With<Player>>,
+    fixed_time: Res<FixedTime>,
+) {
+    (|| {
+        let (transform, mut facing, settings) = facing_query.get_single_mut().ok()?;
+        let settings = settings.unwrap_or(&aim_settings);
 
-        let mouse_pos_2d = wnd.cursor_position()
-            .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor))?;
+        let mouse_pos_2d = action_input.aim?;
 
-        let transform_2d = transform.translation.truncate();
-        let v = mouse_pos_2d - transform_2d;
-        let b = v.normalize();
-        let a = Vec2::new(1.0, 0.0);
-        let new_rotation = Quat::from_rotation_arc_2d(a, b).normalize();
+        let v = mouse_pos_2d - transform.translation.truncate();
+        if v.length_squared() < f32::EPSILON {
+            return None;
+        }
 
-        let old_rotation = transform.rotation;
-        transform.rotation = old_rotation.lerp(new_rotation, 1. - f32::powf(0.002, time.delta_seconds()));
-        //transform.rotation = new_rotation;
+        let target = Facing::from_direction(v).angle();
+        let angle = settings.turn(facing.angle(), target, fixed_time.period.as_secs_f32());
+        facing.set_if_neq(Facing(angle));
 
         Some(())
     })();
 }
 
-pub trait MouseState: Debug + Clone + Copy + PartialEq + Eq + Hash + Sync + Send {}
+/// States aiming can run in, any of the app's states.
+pub trait MouseState: States {}
 
-#[derive(Default)]
-pub struct MousePlugin;
+impl<T: States> MouseState for T {}
+
+/// Tracks the cursor always, and aims while the app is in `active`.
+pub struct MousePlugin<State> {
+    active: State,
+}
+
+impl<State> MousePlugin<State> {
+    pub fn new(active: State) -> MousePlugin<State> {
+        MousePlugin {
+            active,
+        }
+    }
+}
 
-impl Plugin for MousePlugin {
+impl<State: MouseState> Plugin for MousePlugin<State> {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_systems(Update, mouse_look.run_if(in_state(GameState::InGame)));
+        app
+            .init_resource::<AimSettings>()
+            .init_resource::<CursorWorldPosition>()
+            .add_systems(PreUpdate, update_cursor_world_position)
+            .add_systems(
+                FixedUpdate,
+                mouse_look.after(InputSet::Sample).run_if(in_state(self.active.clone())),
+            );
     }
 }
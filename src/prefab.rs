@@ -0,0 +1,106 @@
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+    utils::HashMap,
+};
+use serde::Deserialize;
+
+use crate::{level::{ColliderDef, ContainerDef, StationDef, Visual}, sorting::SortingLayer};
+
+fn yes() -> bool {
+    true
+}
+
+/// Another prefab spawned as a child, relative to its parent.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PrefabChild {
+    pub prefab: String,
+    #[serde(default)]
+    pub offset: Vec2,
+    #[serde(default)]
+    pub z: f32,
+    /// Radians.
+    #[serde(default)]
+    pub rotation: f32,
+}
+
+/// Everything a prop is made of except where it stands.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PrefabDef {
+    pub name: Option<String>,
+    pub visual: Option<Visual>,
+    /// Mirrors the visual's sprite.
+    #[serde(default)]
+    pub flip_x: bool,
+    #[serde(default)]
+    pub flip_y: bool,
+    /// Keeps the z it was placed at when `None`.
+    pub layer: Option<SortingLayer>,
+    /// Sorted by y from this offset when set, see `YSort`.
+    pub pivot: Option<f32>,
+    #[serde(default)]
+    pub colliders: Vec<ColliderDef>,
+    /// Whether the solid colliders block sight as well as movement.
+    #[serde(default = "yes")]
+    pub occluder: bool,
+    pub container: Option<ContainerDef>,
+    pub station: Option<StationDef>,
+    /// Label shown when hovered, for props that aren't containers.
+    pub interactable: Option<String>,
+    #[serde(default)]
+    pub children: Vec<PrefabChild>,
+}
+
+impl Default for PrefabDef {
+    fn default() -> Self {
+        Self {
+            name: None,
+            visual: None,
+            flip_x: false,
+            flip_y: false,
+            layer: None,
+            pivot: None,
+            colliders: vec![],
+            occluder: true,
+            container: None,
+            station: None,
+            interactable: None,
+            children: vec![],
+        }
+    }
+}
+
+/// Named prefabs as read from a `.prefabs.ron` file.
+#[derive(Debug, Deserialize, TypeUuid, TypePath)]
+#[uuid = "a41e6b0d-7c23-4f95-8d1a-56e2f09b3c78"]
+pub struct Prefabs {
+    pub prefabs: HashMap<String, PrefabDef>,
+}
+
+impl Prefabs {
+    pub fn get(&self, name: &str) -> Option<&PrefabDef> {
+        self.prefabs.get(name)
+    }
+}
+
+#[derive(Default)]
+pub struct PrefabsLoader;
+
+impl AssetLoader for PrefabsLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let prefabs: Prefabs = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(prefabs));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["prefabs.ron"]
+    }
+}
@@ -0,0 +1,231 @@
+use std::{collections::VecDeque, env, fs, path::{Path, PathBuf}};
+
+use bevy::{app::AppExit, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{game::GameState, input::{ActionInput, FixedTick, InputSet, InputSource, MovementPlugin, set_fixed_timestep}};
+
+/// Set to a file path to record the action input of this session.
+const RECORD_ENV: &str = "GOO_RECORD_INPUT";
+/// Set to a file path to replay a recording instead of reading the devices.
+const REPLAY_ENV: &str = "GOO_REPLAY_INPUT";
+/// Set to quit once the replay has run out of frames, for automated runs.
+const REPLAY_EXIT_ENV: &str = "GOO_REPLAY_EXIT";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    pub tick: u64,
+    pub input: ActionInput,
+}
+
+/// A recorded session. `timestep` is the fixed period the frames were
+/// sampled at and is restored before replaying.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct InputRecording {
+    pub timestep: f32,
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl InputRecording {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        ron::from_str(&contents).map_err(|e| e.to_string())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Resource)]
+struct InputRecorder {
+    path: PathBuf,
+    recording: InputRecording,
+}
+
+#[derive(Resource)]
+struct InputReplay {
+    frames: VecDeque<RecordedFrame>,
+    exit_when_finished: bool,
+}
+
+fn record_action_input(
+    tick: Res<FixedTick>,
+    action_input: Res<ActionInput>,
+    mut recorder: ResMut<InputRecorder>,
+) {
+    // Only changes are stored, a replay holds the input until the next frame.
+    let frames = &recorder.recording.frames;
+    if frames.last().is_some_and(|frame| frame.input == *action_input) {
+        return;
+    }
+
+    recorder.recording.frames.push(RecordedFrame {
+        tick: tick.0,
+        input: action_input.clone(),
+    });
+}
+
+fn save_recording(
+    mut exit: EventReader<AppExit>,
+    recorder: Res<InputRecorder>,
+) {
+    if exit.iter().next().is_none() {
+        return;
+    }
+
+    match recorder.recording.save(&recorder.path) {
+        Ok(()) => info!("Saved input recording to {:?}", recorder.path),
+        Err(error) => error!("Could not save input recording to {:?}: {}", recorder.path, error),
+    }
+}
+
+fn replay_action_input(
+    tick: Res<FixedTick>,
+    mut replay: ResMut<InputReplay>,
+    mut action_input: ResMut<ActionInput>,
+    mut source: ResMut<InputSource>,
+    mut exit: EventWriter<AppExit>,
+) {
+    while replay.frames.front().is_some_and(|frame| frame.tick <= tick.0) {
+        if let Some(frame) = replay.frames.pop_front() {
+            *action_input = frame.input;
+        }
+    }
+
+    if replay.frames.is_empty() {
+        info!("Input replay finished at tick {}", tick.0);
+        *source = InputSource::Live;
+        if replay.exit_when_finished {
+            exit.send(AppExit);
+        }
+    }
+}
+
+fn replaying(source: Res<InputSource>) -> bool {
+    *source == InputSource::Replay
+}
+
+/// Records the per-tick [`ActionInput`] to the file named by
+/// `GOO_RECORD_INPUT`, or drives the game from the recording named by
+/// `GOO_REPLAY_INPUT`. Must be added after the `MovementPlugin`.
+#[derive(Default)]
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        // the timestep is read from and restored over the one it sets
+        assert!(
+            app.is_plugin_added::<MovementPlugin>(),
+            "ReplayPlugin must be added after MovementPlugin, which sets the fixed timestep",
+        );
+
+        if let Ok(path) = env::var(REPLAY_ENV) {
+            let path = PathBuf::from(path);
+            match InputRecording::load(&path) {
+                Ok(recording) => {
+                    info!("Replaying input from {:?}", path);
+                    set_fixed_timestep(app, recording.timestep);
+                    app
+                        .insert_resource(InputSource::Replay)
+                        .insert_resource(InputReplay {
+                            frames: recording.frames.into(),
+                            exit_when_finished: env::var(REPLAY_EXIT_ENV).is_ok(),
+                        })
+                        .add_systems(FixedUpdate, replay_action_input
+                            .run_if(replaying)
+                            .in_set(InputSet::Sample));
+                }
+                Err(error) => error!("Could not load input recording {:?}: {}", path, error),
+            }
+        } else if let Ok(path) = env::var(RECORD_ENV) {
+            let timestep = app.world.resource::<FixedTime>().period.as_secs_f32();
+            app
+                .insert_resource(InputRecorder {
+                    path: PathBuf::from(path),
+                    recording: InputRecording {
+                        timestep,
+                        frames: vec![],
+                    },
+                })
+                .add_systems(FixedUpdate, record_action_input
+                    .after(InputSet::Sample)
+                    .run_if(in_state(GameState::InGame)))
+                .add_systems(Last, save_recording);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_rapier2d::prelude::KinematicCharacterController;
+
+    use super::*;
+    use crate::{game::Player, input::{PlayerControls, Velocity, FIXED_TIMESTEP, SPEED}, mouse::CursorWorldPosition};
+
+    fn frame(tick: u64, movement: Vec2) -> RecordedFrame {
+        RecordedFrame {
+            tick,
+            input: ActionInput {
+                movement: vec![movement],
+                ..default()
+            },
+        }
+    }
+
+    /// Runs `ticks` fixed ticks of movement driven by `recording`, without
+    /// physics, and returns where the player would end up on an empty level.
+    fn replay(recording: &InputRecording, ticks: u32) -> Vec2 {
+        let mut app = App::new();
+        app
+            .add_state::<GameState>()
+            .add_plugins(MovementPlugin)
+            .add_event::<AppExit>()
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<CursorWorldPosition>()
+            .insert_resource(State::new(GameState::InGame))
+            .insert_resource(InputSource::Replay)
+            .insert_resource(InputReplay {
+                frames: recording.frames.clone().into(),
+                exit_when_finished: false,
+            })
+            .add_systems(FixedUpdate, replay_action_input.run_if(replaying).in_set(InputSet::Sample));
+        let player = app.world.spawn((
+            Player,
+            PlayerControls::default(),
+            Velocity::default(),
+            KinematicCharacterController::default(),
+            Transform::default(),
+        )).id();
+
+        for _ in 0..ticks {
+            app.world.run_schedule(FixedUpdate);
+        }
+
+        let player = app.world.entity(player);
+        let translation = player.get::<KinematicCharacterController>().and_then(|controller| controller.translation);
+        player.get::<Transform>().unwrap().translation.truncate() + translation.unwrap_or_default()
+    }
+
+    #[test]
+    fn replay_moves_player() {
+        let recording = InputRecording {
+            timestep: FIXED_TIMESTEP,
+            frames: vec![
+                frame(1, Vec2::X),
+                frame(31, Vec2::Y),
+                frame(61, Vec2::ZERO),
+            ],
+        };
+
+        let end = replay(&recording, 90);
+        let expected = Vec2::splat(30. * SPEED * FIXED_TIMESTEP);
+        assert!(end.abs_diff_eq(expected, 1e-3), "ended at {end}, expected {expected}");
+
+        // a saved and loaded recording ends up in the same place
+        let loaded: InputRecording = ron::from_str(&ron::to_string(&recording).unwrap()).unwrap();
+        assert_eq!(replay(&loaded, 90), end);
+    }
+}
@@ -1,65 +1,283 @@
-use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
+use bevy::{prelude::*, ecs::system::EntityCommands, sprite::{Anchor, MaterialMesh2dBundle}};
 use bevy_rapier2d::prelude::*;
 
-use crate::{loading::GameAssets, inventory::{ItemOnGround, InventoryItemType, Item}};
+use crate::{loading::GameAssets, inventory::{Inventory, ItemStack, spawn_item_on_ground}, items::ItemRegistry, loot::{LootRng, LootTables}, container::container_bundle, crafting::CraftingStation, interaction::Interactable, level::{Level, Visual, ColliderDef, ShapeDef}, auto_collider::AutoCollider, sorting::YSort, prefab::{PrefabDef, Prefabs}, camera::{BoundsSource, LevelBounds}};
 
+/// Deepest prefab nesting, so a prefab containing itself can't hang the game.
+const MAX_PREFAB_DEPTH: usize = 8;
+
+/// Spawns the backgrounds, props and items of the level.
 pub fn setup_scene(
     mut commands: Commands,
     game_assets: Res<GameAssets>,
+    asset_server: Res<AssetServer>,
+    levels: Res<Assets<Level>>,
+    prefabs: Res<Assets<Prefabs>>,
+    registry: Res<ItemRegistry>,
+    loot_tables: Res<Assets<LootTables>>,
+    mut loot_rng: ResMut<LootRng>,
+    mut level_bounds: ResMut<LevelBounds>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
-    commands
-        .spawn((
+    let Some(level) = levels.get(&game_assets.level) else {
+        warn!("level not loaded, nothing to spawn");
+        return;
+    };
+    let prefabs = prefabs.get(&game_assets.prefabs);
+
+    // explicit bounds win over the ones derived from the backgrounds
+    if level.bounds.is_some() {
+        *level_bounds = LevelBounds(level.bounds);
+    }
+
+    for background in level.backgrounds.iter() {
+        let mut entity = commands.spawn((
             SpriteBundle {
-                texture: game_assets.tent_texture.clone(),
-                transform: Transform::from_translation(Vec3::new(500., -300., 1.)),
+                sprite: Sprite {
+                    anchor: match background.corner {
+                        true => Anchor::TopLeft,
+                        false => Anchor::Center,
+                    },
+                    ..default()
+                },
+                texture: asset_server.load(background.texture.as_str()),
+                transform: Transform::from_translation(background.position.extend(background.z))
+                    .with_scale(Vec3::splat(background.scale)),
                 ..default()
             },
-        ))
-        .with_children(|parent| {
-            parent.spawn((
-                Collider::cuboid(1., 83.),
-                TransformBundle::from_transform(
-                    Transform::from_xyz(0., 5., 1.)
-                    .with_rotation(Quat::from_rotation_z(-0.35))
-                ),
-                CollisionGroups::new(Group::GROUP_1, Group::ALL),
-                //Name::new("Tent collider"),
-            ));
-        });
-
-    commands.spawn((
-        MaterialMesh2dBundle {
-            mesh: meshes
-                .add(shape::Quad::new(Vec2::new(50., 50.)).into())
-                .into(),
-            material: materials.add(ColorMaterial::from(Color::LIME_GREEN)),
-            transform: Transform::from_translation(Vec3::new(200., 0., 1.)),
-            ..default()
-        },
-        Collider::cuboid(25., 25.),
-        CollisionGroups::new(Group::GROUP_1, Group::ALL),
-        //Name::new("Column"),
-    ));
-
-    commands.spawn((
-        MaterialMesh2dBundle {
-            mesh: meshes
-                .add(shape::Quad::new(Vec2::new(50., 50.)).into())
-                .into(),
-            material: materials.add(ColorMaterial::from(Color::LIME_GREEN)),
-            transform: Transform::from_translation(Vec3::new(-50., -50., 1.)),
-            ..default()
-        },
-        ItemOnGround {
-            item: Item {
-                name: "ItemA".into(),
-                item_type: InventoryItemType::Consumable,
+            Name::new("bg"),
+        ));
+        if level.bounds.is_none() {
+            entity.insert(BoundsSource);
+        }
+    }
+
+    let atlases: Vec<Handle<TextureAtlas>> = level.tilesets.iter()
+        .map(|tileset| texture_atlases.add(TextureAtlas::from_grid(
+            asset_server.load(tileset.image.as_str()),
+            tileset.tile_size,
+            tileset.columns,
+            tileset.rows,
+            Some(Vec2::splat(tileset.spacing)),
+            Some(Vec2::splat(tileset.margin)),
+        )))
+        .collect();
+
+    for layer in level.tile_layers.iter() {
+        commands
+            .spawn((
+                SpatialBundle::from_transform(Transform::from_xyz(0., 0., layer.z)),
+                Name::new(layer.name.clone()),
+            ))
+            .with_children(|parent| {
+                // sprites sharing an atlas are drawn in one batch
+                for tile in layer.tiles.iter() {
+                    let Some(atlas) = atlas_tile(&texture_atlases, &atlases, tile.tileset, tile.index) else {
+                        continue;
+                    };
+                    parent.spawn(SpriteSheetBundle {
+                        sprite: TextureAtlasSprite {
+                            index: tile.index,
+                            flip_x: tile.flip_x,
+                            flip_y: tile.flip_y,
+                            ..default()
+                        },
+                        texture_atlas: atlas.clone(),
+                        transform: Transform::from_translation(tile.position.extend(0.)),
+                        ..default()
+                    });
+                }
+            });
+    }
+
+    let mut spawner = PrefabSpawner {
+        asset_server: &asset_server,
+        meshes: &mut meshes,
+        materials: &mut materials,
+        texture_atlases: &texture_atlases,
+        atlases: &atlases,
+        prefabs,
+        loot_tables: loot_tables.get(&game_assets.loot_tables),
+        registry: &registry,
+        loot_rng: &mut loot_rng,
+    };
+    for prop in level.props.iter() {
+        let transform = Transform::from_translation(prop.position.extend(prop.z))
+            .with_rotation(Quat::from_rotation_z(prop.rotation));
+        let entity = commands.spawn_empty().id();
+        spawner.spawn(&mut commands, entity, &prop.resolve(prefabs), transform, 0);
+    }
+
+    for item in level.items.iter() {
+        spawn_item_on_ground(&mut commands, &registry, ItemStack::new(item.item.clone(), item.quantity), item.position);
+    }
+}
+
+/// The atlas of `tileset`, if it has a tile `index`.
+fn atlas_tile<'a>(texture_atlases: &Assets<TextureAtlas>, atlases: &'a [Handle<TextureAtlas>], tileset: usize, index: usize) -> Option<&'a Handle<TextureAtlas>> {
+    let atlas = atlases.get(tileset)?;
+    let len = texture_atlases.get(atlas).map_or(0, TextureAtlas::len);
+    if index >= len {
+        warn!("tileset {tileset} has no tile {index}");
+        return None;
+    }
+    Some(atlas)
+}
+
+/// What spawning a prefab needs besides the commands.
+struct PrefabSpawner<'a> {
+    asset_server: &'a AssetServer,
+    meshes: &'a mut Assets<Mesh>,
+    materials: &'a mut Assets<ColorMaterial>,
+    texture_atlases: &'a Assets<TextureAtlas>,
+    atlases: &'a [Handle<TextureAtlas>],
+    prefabs: Option<&'a Prefabs>,
+    loot_tables: Option<&'a LootTables>,
+    registry: &'a ItemRegistry,
+    loot_rng: &'a mut LootRng,
+}
+
+impl PrefabSpawner<'_> {
+    fn insert_collider(&self, entity: &mut EntityCommands, collider: &ColliderDef, prefab: &PrefabDef) {
+        entity.insert(collider.layer.groups(prefab.occluder));
+        if collider.layer.is_sensor() {
+            entity.insert(Sensor);
+        }
+
+        match (&collider.shape, &prefab.visual) {
+            (ShapeDef::Traced(settings), Some(Visual::Texture(path))) => {
+                entity.insert(AutoCollider {
+                    image: self.asset_server.load(path.as_str()),
+                    size: None,
+                    settings: *settings,
+                });
+            }
+            (ShapeDef::Traced(_), _) => warn!("traced colliders need a texture to trace"),
+            (shape, _) => {
+                if let Some(shape) = shape.collider() {
+                    entity.insert(shape);
+                }
+            }
+        }
+    }
+
+    /// Builds `prefab` on `entity`, then its children below it.
+    fn spawn(&mut self, commands: &mut Commands, entity: Entity, prefab: &PrefabDef, transform: Transform, depth: usize) {
+        let mut entity = commands.entity(entity);
+        match &prefab.visual {
+            Some(Visual::Texture(path)) => entity.insert(SpriteBundle {
+                sprite: Sprite {
+                    flip_x: prefab.flip_x,
+                    flip_y: prefab.flip_y,
+                    ..default()
+                },
+                texture: self.asset_server.load(path.as_str()),
+                transform,
+                ..default()
+            }),
+            Some(Visual::Rect { color, size }) => entity.insert(SpriteBundle {
+                sprite: Sprite {
+                    color: *color,
+                    custom_size: Some(*size),
+                    flip_x: prefab.flip_x,
+                    flip_y: prefab.flip_y,
+                    ..default()
+                },
+                transform,
+                ..default()
+            }),
+            Some(Visual::Circle { color, radius }) => entity.insert(MaterialMesh2dBundle {
+                mesh: self.meshes.add(shape::Circle::new(*radius).into()).into(),
+                material: self.materials.add(ColorMaterial::from(*color)),
+                transform,
+                ..default()
+            }),
+            Some(Visual::Tile { tileset, index, size }) => match atlas_tile(self.texture_atlases, self.atlases, *tileset, *index) {
+                Some(atlas) => entity.insert(SpriteSheetBundle {
+                    sprite: TextureAtlasSprite {
+                        index: *index,
+                        custom_size: *size,
+                        flip_x: prefab.flip_x,
+                        flip_y: prefab.flip_y,
+                        ..default()
+                    },
+                    texture_atlas: atlas.clone(),
+                    transform,
+                    ..default()
+                }),
+                None => entity.insert(SpatialBundle::from_transform(transform)),
             },
-        },
-        Collider::cuboid(15., 15.),
-        CollisionGroups::new(Group::GROUP_2, Group::ALL),
-        //Name::new("ItemA on ground"),
-    ));
+            None => entity.insert(SpatialBundle::from_transform(transform)),
+        };
+
+        if let Some(name) = &prefab.name {
+            entity.insert(Name::new(name.clone()));
+        }
+        if let Some(pivot) = prefab.pivot {
+            entity.insert((YSort { pivot }, prefab.layer.unwrap_or_default()));
+        } else if let Some(layer) = prefab.layer {
+            entity.insert(layer);
+        }
+        if let Some(station) = &prefab.station {
+            entity.insert(CraftingStation {
+                kind: station.kind.clone(),
+                radius: station.radius,
+            });
+        }
+        if let Some(label) = &prefab.interactable {
+            entity.insert(Interactable {
+                label: label.clone(),
+            });
+        }
+        if let Some(container) = &prefab.container {
+            let mut inventory = Inventory::new(container.slots);
+            let table = container.loot.as_ref()
+                .and_then(|table| self.loot_tables.and_then(|tables| tables.get(table)));
+            if let Some(table) = table {
+                table.fill(&mut inventory, self.registry, &mut self.loot_rng.0);
+            }
+            entity.insert(container_bundle(&container.name, inventory));
+        }
+
+        // a single centered collider goes on the prop itself, so hovering and
+        // sight checks find the prop directly
+        match prefab.colliders.as_slice() {
+            [collider] if collider.is_centered() => {
+                self.insert_collider(&mut entity, collider, prefab);
+            }
+            colliders => {
+                entity.with_children(|parent| {
+                    for collider in colliders {
+                        let mut child = parent.spawn(TransformBundle::from_transform(
+                            Transform::from_translation(collider.offset.extend(0.))
+                                .with_rotation(Quat::from_rotation_z(collider.rotation))
+                        ));
+                        self.insert_collider(&mut child, collider, prefab);
+                    }
+                });
+            }
+        }
+
+        let parent = entity.id();
+        if prefab.children.is_empty() {
+            return;
+        }
+        if depth >= MAX_PREFAB_DEPTH {
+            warn!("prefabs nested deeper than {MAX_PREFAB_DEPTH}, skipping children");
+            return;
+        }
+        for child in prefab.children.iter() {
+            let Some(child_prefab) = self.prefabs.and_then(|prefabs| prefabs.get(&child.prefab)) else {
+                warn!("unknown prefab {}", child.prefab);
+                continue;
+            };
+            let transform = Transform::from_translation(child.offset.extend(child.z))
+                .with_rotation(Quat::from_rotation_z(child.rotation));
+            let entity = commands.spawn_empty().id();
+            commands.entity(parent).add_child(entity);
+            self.spawn(commands, entity, child_prefab, transform, depth + 1);
+        }
+    }
 }
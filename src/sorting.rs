@@ -0,0 +1,158 @@
+use bevy::{prelude::*, transform::TransformSystem};
+use serde::Deserialize;
+
+use crate::game::GameState;
+
+/// How much z changes per world unit of y. Small enough that any level fits
+/// inside a layer's band.
+const Z_PER_Y: f32 = 1e-4;
+/// Puts characters in front of props standing at the same y.
+const CHARACTER_BIAS: f32 = Z_PER_Y / 2.;
+
+/// Broad draw order. Backgrounds and tile layers stay below all of these.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum SortingLayer {
+    /// Things lying flat, like items on the ground.
+    Ground,
+    /// Props and characters share a band so they can be drawn in front of or
+    /// behind each other.
+    #[default]
+    Props,
+    Characters,
+    /// Drawn over everything, like treetops.
+    Overhead,
+}
+
+impl SortingLayer {
+    pub fn z(self) -> f32 {
+        match self {
+            SortingLayer::Ground => 1.,
+            SortingLayer::Props => 10.,
+            SortingLayer::Characters => 10. + CHARACTER_BIAS,
+            SortingLayer::Overhead => 20.,
+        }
+    }
+}
+
+/// Draws lower entities in front, judged by the point `pivot` above the
+/// origin, usually where the sprite touches the ground.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq)]
+pub struct YSort {
+    pub pivot: f32,
+}
+
+/// Where `entity` is in the world going by the `Transform`s up its hierarchy,
+/// as `GlobalTransform` is only brought up to date after sorting.
+fn world_transform(transforms: &Query<(&mut Transform, Option<&Parent>)>, entity: Entity) -> GlobalTransform {
+    let mut world = GlobalTransform::IDENTITY;
+    let mut next = Some(entity);
+    while let Some((transform, parent)) = next.and_then(|entity| transforms.get(entity).ok()) {
+        world = GlobalTransform::from(*transform) * world;
+        next = parent.map(Parent::get);
+    }
+    world
+}
+
+fn depth(transforms: &Query<(&mut Transform, Option<&Parent>)>, entity: Entity) -> usize {
+    let mut depth = 0;
+    let mut next = entity;
+    while let Some(parent) = transforms.get(next).ok().and_then(|(_, parent)| parent) {
+        depth += 1;
+        next = parent.get();
+    }
+    depth
+}
+
+/// Sets the z of everything with a sorting layer. Children are given the
+/// local z that puts them at the right depth in the world.
+fn sort_by_layer(
+    sorted: Query<(Entity, Option<&SortingLayer>, Option<&YSort>), Or<(With<SortingLayer>, With<YSort>)>>,
+    mut transforms: Query<(&mut Transform, Option<&Parent>)>,
+) {
+    // parents first, so their children see the z they end up with
+    let mut sorted: Vec<_> = sorted.iter().collect();
+    sorted.sort_by_cached_key(|(entity, ..)| depth(&transforms, *entity));
+
+    for (entity, layer, y_sort) in sorted {
+        let parent = transforms.get(entity).ok()
+            .and_then(|(_, parent)| parent)
+            .map(|parent| world_transform(&transforms, parent.get()));
+        let Ok((mut transform, _)) = transforms.get_mut(entity) else {
+            continue;
+        };
+        let world = parent.map_or(transform.translation, |parent| parent.transform_point(transform.translation));
+
+        let mut z = layer.copied().unwrap_or_default().z();
+        if let Some(y_sort) = y_sort {
+            z -= (world.y + y_sort.pivot) * Z_PER_Y;
+        }
+        let z = z - parent.map_or(0., |parent| parent.translation().z);
+
+        if transform.translation.z != z {
+            transform.translation.z = z;
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SortingPlugin;
+
+impl Plugin for SortingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PostUpdate, sort_by_layer
+            .before(TransformSystem::TransformPropagate)
+            .run_if(in_state(GameState::InGame)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn z(app: &App, entity: Entity) -> f32 {
+        app.world.get::<GlobalTransform>(entity).unwrap().translation().z
+    }
+
+    #[test]
+    fn children_sort_in_the_world() {
+        let mut app = App::new();
+        app
+            .add_state::<GameState>()
+            .insert_resource(State::new(GameState::InGame))
+            .add_plugins((TransformPlugin, SortingPlugin));
+
+        let tent = app.world.spawn((
+            TransformBundle::from_transform(Transform::from_xyz(0., 100., 5.)),
+            SortingLayer::Props,
+            YSort::default(),
+        )).id();
+        let camper = app.world.spawn((
+            TransformBundle::from_transform(Transform::from_xyz(0., -10., 0.)),
+            SortingLayer::Characters,
+            YSort::default(),
+        )).id();
+        app.world.entity_mut(tent).add_child(camper);
+        let treetop = app.world.spawn((
+            TransformBundle::from_transform(Transform::from_xyz(0., 1000., 0.)),
+            SortingLayer::Overhead,
+        )).id();
+        let rock = app.world.spawn((
+            TransformBundle::from_transform(Transform::from_xyz(0., 90., 0.)),
+            SortingLayer::Props,
+            YSort::default(),
+        )).id();
+        app.update();
+
+        assert!((z(&app, tent) - (10. - 100. * Z_PER_Y)).abs() < 1e-5, "{}", z(&app, tent));
+        assert!((z(&app, camper) - (10. + CHARACTER_BIAS - 90. * Z_PER_Y)).abs() < 1e-5, "{}", z(&app, camper));
+        assert!(z(&app, camper) > z(&app, rock), "characters go in front of props at the same y");
+        assert!(z(&app, camper) > z(&app, tent));
+        assert!(z(&app, treetop) > z(&app, camper));
+
+        // the child is sorted from where its parent is this frame
+        app.world.get_mut::<Transform>(tent).unwrap().translation.y = 50.;
+        app.update();
+        assert!((z(&app, camper) - (10. + CHARACTER_BIAS - 40. * Z_PER_Y)).abs() < 1e-5, "{}", z(&app, camper));
+        assert!(z(&app, camper) > z(&app, rock));
+    }
+}
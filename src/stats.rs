@@ -0,0 +1,82 @@
+use bevy::prelude::*;
+
+use crate::{
+    equipment::Equipment,
+    field_of_view::{FOV_ANGLE, FOV_VIEW_DISTANCE},
+    game::GameState,
+    input::SPEED,
+    items::{InventoryItemType, ItemRegistry},
+};
+
+const BASE_MAX_HEALTH: f32 = 100.;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StatBlock {
+    pub speed: f32,
+    pub fov_range: f32,
+    /// Half of the vision cone, in radians.
+    pub fov_angle: f32,
+    pub max_health: f32,
+    /// Radius lit around the entity regardless of where it looks.
+    pub light_radius: f32,
+}
+
+impl Default for StatBlock {
+    fn default() -> Self {
+        Self {
+            speed: SPEED,
+            fov_range: FOV_VIEW_DISTANCE,
+            fov_angle: FOV_ANGLE,
+            max_health: BASE_MAX_HEALTH,
+            light_radius: 0.,
+        }
+    }
+}
+
+/// Stats before equipment is taken into account.
+#[derive(Component, Clone, Copy, Default, Debug)]
+pub struct BaseStats(pub StatBlock);
+
+/// Stats after equipment, recomputed whenever the equipment changes. Read
+/// this one from gameplay systems.
+#[derive(Component, Clone, Copy, Default, Debug, PartialEq, Deref)]
+pub struct Stats(pub StatBlock);
+
+fn recompute_stats(
+    registry: Res<ItemRegistry>,
+    mut query: Query<(Ref<BaseStats>, Ref<Equipment>, &mut Stats)>,
+) {
+    for (base, equipment, mut stats) in query.iter_mut() {
+        if !(base.is_changed() || equipment.is_changed() || registry.is_changed()) {
+            continue;
+        }
+
+        let mut block = base.0;
+        let mut speed_bonus = 0.;
+        for (_, item_id) in equipment.iter() {
+            let Some(InventoryItemType::Equipment(equipment_stats)) = registry.get(item_id).map(|definition| &definition.item_type) else {
+                continue;
+            };
+            let modifiers = &equipment_stats.modifiers;
+            speed_bonus += modifiers.speed;
+            block.fov_range += modifiers.fov_range;
+            block.fov_angle += modifiers.fov_angle_degrees.to_radians();
+            block.max_health += modifiers.max_health;
+            block.light_radius += modifiers.light_radius;
+        }
+        block.speed *= (1. + speed_bonus).max(0.);
+        block.fov_range = block.fov_range.max(0.);
+        block.fov_angle = block.fov_angle.clamp(0., std::f32::consts::PI);
+
+        stats.set_if_neq(Stats(block));
+    }
+}
+
+#[derive(Default)]
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, recompute_stats.run_if(in_state(GameState::InGame)));
+    }
+}
@@ -0,0 +1,754 @@
+//! Reads maps made in Tiled, saved as JSON (`.tmj`), into a [`Level`].
+//!
+//! - Tile layers must use CSV encoding.
+//! - Tilesets must be embedded in the map.
+//! - The XML formats (`.tmx`, `.tsx`) aren't supported.
+//! - Hidden layers, and everything in hidden groups, are left out.
+//!
+//! What becomes what:
+//! - Image layers become backgrounds.
+//! - Point objects become spawn points, named after the object.
+//! - Objects of type `item` become items on the ground, using the `item`
+//!   and `quantity` properties.
+//! - Every other object becomes a prop. Rectangles, ellipses, polygons and
+//!   polylines give it a collider, and tile objects give it a sprite.
+//!
+//! Prop properties:
+//! - `prefab`: the prefab the prop starts from, the object's shape, tile
+//!   and other properties replace the prefab's.
+//! - `layer`: `Solid` or `Walkable`.
+//! - `occluder`: whether solid colliders block sight.
+//! - `sorting_layer`: `Ground`, `Props`, `Characters` or `Overhead`.
+//! - `pivot`: y sorts the prop from this offset.
+//! - `z`.
+//! - `container`, `slots` and `loot`.
+//! - `station` and `station_radius`.
+//! - `interactable`.
+//!
+//! `assets/maps/fixture.tmj` is a small map using all of these.
+
+use std::{fmt::{self, Display, Formatter}, path::{Component, Path, PathBuf}};
+
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{items::ItemId, sorting::SortingLayer, level::{Level, BackgroundDef, TilesetDef, TileDef, TileLayerDef, PropDef, ColliderDef, ColliderLayer, ShapeDef, Visual, ContainerDef, StationDef, ItemSpawn}};
+
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const FLIP_FLAGS: u32 = 0xF000_0000;
+
+const ITEM_TYPE: &str = "item";
+const DEFAULT_STATION_RADIUS: f32 = 80.;
+/// Gap between the z of consecutive tile layers, all below the props.
+const TILE_LAYER_Z_STEP: f32 = 0.01;
+
+#[derive(Debug)]
+pub enum TiledError {
+    ExternalTileset(String),
+    MissingTileset(u32),
+    TileOutOfRange(u32),
+    InvalidProperty(String),
+    /// A tile layer with a width of 0, holding the layer's name.
+    ZeroWidth(String),
+}
+
+impl Display for TiledError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TiledError::ExternalTileset(source) => write!(f, "external tileset {source} isn't supported, embed it in the map"),
+            TiledError::MissingTileset(gid) => write!(f, "no tileset has tile {gid}"),
+            TiledError::TileOutOfRange(gid) => write!(f, "tile {gid} is past the end of its tileset"),
+            TiledError::InvalidProperty(name) => write!(f, "property {name} has the wrong type"),
+            TiledError::ZeroWidth(name) => write!(f, "tile layer {name} has a width of 0"),
+        }
+    }
+}
+
+impl std::error::Error for TiledError {}
+
+#[derive(Debug, Deserialize)]
+struct Property {
+    name: String,
+    value: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct Point {
+    x: f32,
+    y: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct TilesetTile {
+    id: u32,
+    image: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Tileset {
+    firstgid: u32,
+    source: Option<String>,
+    image: Option<String>,
+    #[serde(default)]
+    tilewidth: f32,
+    #[serde(default)]
+    tileheight: f32,
+    #[serde(default)]
+    columns: u32,
+    #[serde(default)]
+    tilecount: u32,
+    #[serde(default)]
+    spacing: f32,
+    #[serde(default)]
+    margin: f32,
+    /// Per tile images, for image collection tilesets.
+    #[serde(default)]
+    tiles: Vec<TilesetTile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Object {
+    #[serde(default)]
+    name: String,
+    #[serde(default, alias = "class")]
+    r#type: String,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    /// Degrees, clockwise.
+    #[serde(default)]
+    rotation: f32,
+    gid: Option<u32>,
+    #[serde(default)]
+    point: bool,
+    #[serde(default)]
+    ellipse: bool,
+    polygon: Option<Vec<Point>>,
+    polyline: Option<Vec<Point>>,
+    #[serde(default)]
+    properties: Vec<Property>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Layer {
+    TileLayer {
+        #[serde(default)]
+        name: String,
+        width: u32,
+        data: Vec<u32>,
+        #[serde(default)]
+        offsetx: f32,
+        #[serde(default)]
+        offsety: f32,
+        #[serde(default = "visible")]
+        visible: bool,
+    },
+    ObjectGroup {
+        objects: Vec<Object>,
+        #[serde(default)]
+        offsetx: f32,
+        #[serde(default)]
+        offsety: f32,
+        #[serde(default = "visible")]
+        visible: bool,
+    },
+    ImageLayer {
+        image: String,
+        #[serde(default)]
+        offsetx: f32,
+        #[serde(default)]
+        offsety: f32,
+        #[serde(default = "visible")]
+        visible: bool,
+    },
+    Group {
+        layers: Vec<Layer>,
+        #[serde(default)]
+        offsetx: f32,
+        #[serde(default)]
+        offsety: f32,
+        #[serde(default = "visible")]
+        visible: bool,
+    },
+}
+
+fn visible() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+struct Map {
+    width: u32,
+    height: u32,
+    tilewidth: f32,
+    tileheight: f32,
+    layers: Vec<Layer>,
+    #[serde(default)]
+    tilesets: Vec<Tileset>,
+}
+
+fn property<'a>(properties: &'a [Property], name: &str) -> Option<&'a Value> {
+    properties.iter().find(|property| property.name == name).map(|property| &property.value)
+}
+
+fn string_property(properties: &[Property], name: &str) -> Result<Option<String>, TiledError> {
+    property(properties, name)
+        .map(|value| value.as_str().map(String::from).ok_or_else(|| TiledError::InvalidProperty(name.into())))
+        .transpose()
+}
+
+fn float_property(properties: &[Property], name: &str) -> Result<Option<f32>, TiledError> {
+    property(properties, name)
+        .map(|value| value.as_f64().map(|value| value as f32).ok_or_else(|| TiledError::InvalidProperty(name.into())))
+        .transpose()
+}
+
+fn bool_property(properties: &[Property], name: &str) -> Result<Option<bool>, TiledError> {
+    property(properties, name)
+        .map(|value| value.as_bool().ok_or_else(|| TiledError::InvalidProperty(name.into())))
+        .transpose()
+}
+
+fn int_property(properties: &[Property], name: &str) -> Result<Option<u32>, TiledError> {
+    property(properties, name)
+        .map(|value| value.as_u64().map(|value| value as u32).ok_or_else(|| TiledError::InvalidProperty(name.into())))
+        .transpose()
+}
+
+/// Joins `relative` onto `base`, folding away `..`, as the asset server
+/// wants paths without them.
+fn resolve(base: &Path, relative: &str) -> String {
+    let mut path = PathBuf::new();
+    for component in base.join(relative).components() {
+        match component {
+            Component::ParentDir => {
+                path.pop();
+            }
+            Component::CurDir => {}
+            component => path.push(component),
+        }
+    }
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Builds up a [`Level`] while walking the map's layers.
+struct Converter<'a> {
+    map: &'a Map,
+    directory: &'a Path,
+    /// Index of each map tileset in `level.tilesets`, `None` for image
+    /// collections.
+    tileset_indices: Vec<Option<usize>>,
+    level: Level,
+    tile_layers: usize,
+}
+
+impl<'a> Converter<'a> {
+    fn new(map: &'a Map, directory: &'a Path) -> Result<Self, TiledError> {
+        let size = Vec2::new(map.width as f32 * map.tilewidth, map.height as f32 * map.tileheight);
+        let mut level = Level {
+            bounds: Some(Rect::from_center_size(Vec2::ZERO, size)),
+            backgrounds: vec![],
+            tilesets: vec![],
+            tile_layers: vec![],
+            props: vec![],
+            items: vec![],
+            spawn_points: default(),
+        };
+
+        let mut tileset_indices = vec![];
+        for tileset in map.tilesets.iter() {
+            if let Some(source) = &tileset.source {
+                return Err(TiledError::ExternalTileset(source.clone()));
+            }
+            let index = tileset.image.as_ref().map(|image| {
+                let columns = tileset.columns.max(1);
+                level.tilesets.push(TilesetDef {
+                    image: resolve(directory, image),
+                    tile_size: Vec2::new(tileset.tilewidth, tileset.tileheight),
+                    columns: columns as usize,
+                    rows: tileset.tilecount.div_ceil(columns) as usize,
+                    spacing: tileset.spacing,
+                    margin: tileset.margin,
+                });
+                level.tilesets.len() - 1
+            });
+            tileset_indices.push(index);
+        }
+
+        Ok(Converter {
+            map,
+            directory,
+            tileset_indices,
+            level,
+            tile_layers: 0,
+        })
+    }
+
+    /// Tiled measures from the top left corner with y pointing down, levels
+    /// from the map's center with y pointing up.
+    fn to_world(&self, x: f32, y: f32) -> Vec2 {
+        let size = Vec2::new(self.map.width as f32 * self.map.tilewidth, self.map.height as f32 * self.map.tileheight);
+        Vec2::new(x - size.x / 2., size.y / 2. - y)
+    }
+
+    /// The tileset holding `gid` and the tile's index in it.
+    fn tileset(&self, gid: u32) -> Result<(usize, u32), TiledError> {
+        let (index, tileset) = self.map.tilesets.iter()
+            .enumerate()
+            .filter(|(_, tileset)| tileset.firstgid <= gid)
+            .max_by_key(|(_, tileset)| tileset.firstgid)
+            .ok_or(TiledError::MissingTileset(gid))?;
+
+        // ids in image collections can have gaps, so look them up instead
+        let id = gid - tileset.firstgid;
+        let exists = match tileset.image {
+            Some(_) => id < tileset.tilecount,
+            None => tileset.tiles.iter().any(|tile| tile.id == id),
+        };
+        if !exists {
+            return Err(TiledError::TileOutOfRange(gid));
+        }
+        Ok((index, id))
+    }
+
+    fn tile_visual(&self, gid: u32, size: Vec2) -> Result<Option<Visual>, TiledError> {
+        let (tileset, index) = self.tileset(gid & !FLIP_FLAGS)?;
+        if let Some(atlas) = self.tileset_indices[tileset] {
+            return Ok(Some(Visual::Tile {
+                tileset: atlas,
+                index: index as usize,
+                size: Some(size),
+            }));
+        }
+
+        let image = self.map.tilesets[tileset].tiles.iter()
+            .find(|tile| tile.id == index)
+            .and_then(|tile| tile.image.as_ref());
+        Ok(image.map(|image| Visual::Texture(resolve(self.directory, image))))
+    }
+
+    /// Converts `layer`, shifted by `offset`, the offsets of the groups it
+    /// is in added up.
+    fn layer(&mut self, layer: &Layer, offset: Vec2) -> Result<(), TiledError> {
+        match layer {
+            Layer::TileLayer { name, width, data, offsetx, offsety, visible: true } => {
+                self.tile_layer(name, *width, data, offset + Vec2::new(*offsetx, *offsety))?;
+            }
+            Layer::ObjectGroup { objects, offsetx, offsety, visible: true } => {
+                for object in objects {
+                    self.object(object, offset + Vec2::new(*offsetx, *offsety))?;
+                }
+            }
+            Layer::ImageLayer { image, offsetx, offsety, visible: true } => {
+                self.level.backgrounds.push(BackgroundDef {
+                    texture: resolve(self.directory, image),
+                    position: self.to_world(offset.x + offsetx, offset.y + offsety),
+                    scale: 1.,
+                    z: 0.,
+                    corner: true,
+                });
+            }
+            Layer::Group { layers, offsetx, offsety, visible: true } => {
+                for layer in layers {
+                    self.layer(layer, offset + Vec2::new(*offsetx, *offsety))?;
+                }
+            }
+            // hidden
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn tile_layer(&mut self, name: &str, width: u32, data: &[u32], offset: Vec2) -> Result<(), TiledError> {
+        if width == 0 {
+            return Err(TiledError::ZeroWidth(name.into()));
+        }
+
+        let mut tiles = vec![];
+        for (i, &gid) in data.iter().enumerate().filter(|(_, &gid)| gid != 0) {
+            let (tileset, index) = self.tileset(gid & !FLIP_FLAGS)?;
+            let Some(atlas) = self.tileset_indices[tileset] else {
+                continue;
+            };
+
+            // tiles bigger than the grid stick out upwards from the cell's
+            // bottom left corner
+            let tile_size = self.level.tilesets[atlas].tile_size;
+            let (column, row) = ((i as u32 % width) as f32, (i as u32 / width) as f32);
+            let corner = Vec2::new(column * self.map.tilewidth, (row + 1.) * self.map.tileheight) + offset;
+            tiles.push(TileDef {
+                tileset: atlas,
+                index: index as usize,
+                position: self.to_world(corner.x + tile_size.x / 2., corner.y - tile_size.y / 2.),
+                flip_x: gid & FLIPPED_HORIZONTALLY != 0,
+                flip_y: gid & FLIPPED_VERTICALLY != 0,
+            });
+        }
+
+        self.level.tile_layers.push(TileLayerDef {
+            name: name.into(),
+            z: self.tile_layers as f32 * TILE_LAYER_Z_STEP,
+            tiles,
+        });
+        self.tile_layers += 1;
+        Ok(())
+    }
+
+    fn object(&mut self, object: &Object, offset: Vec2) -> Result<(), TiledError> {
+        let origin = self.to_world(object.x + offset.x, object.y + offset.y);
+
+        if object.point {
+            self.level.spawn_points.insert(object.name.clone(), origin);
+            return Ok(());
+        }
+
+        let properties = &object.properties;
+        if object.r#type == ITEM_TYPE {
+            let Some(item) = string_property(properties, "item")? else {
+                warn!("item object {} has no item property", object.name);
+                return Ok(());
+            };
+            self.level.items.push(ItemSpawn {
+                item: ItemId(item),
+                quantity: int_property(properties, "quantity")?.unwrap_or(1),
+                position: origin + Vec2::new(object.width, -object.height) / 2.,
+            });
+            return Ok(());
+        }
+
+        let rotation = -object.rotation.to_radians();
+        let size = Vec2::new(object.width, object.height);
+        let layer = match string_property(properties, "layer")?.as_deref() {
+            Some("Walkable") => ColliderLayer::Walkable,
+            Some("Solid") | None => ColliderLayer::Solid,
+            Some(_) => return Err(TiledError::InvalidProperty("layer".into())),
+        };
+
+        // tile objects hang up from their bottom left corner, shapes down
+        // from their top left one, both rotating around that corner
+        let (position, visual, shape) = if let Some(gid) = object.gid {
+            let center = origin + Quat::from_rotation_z(rotation).mul_vec3(Vec3::new(size.x / 2., size.y / 2., 0.)).truncate();
+            (center, self.tile_visual(gid, size)?, None)
+        } else if let Some(points) = object.polygon.as_ref().or(object.polyline.as_ref()) {
+            let points: Vec<Vec2> = points.iter().map(|point| Vec2::new(point.x, -point.y)).collect();
+            let shape = match object.polygon.is_some() {
+                true => ShapeDef::Polygon { points },
+                false => ShapeDef::Polyline { points },
+            };
+            (origin, None, Some(shape))
+        } else {
+            let center = origin + Quat::from_rotation_z(rotation).mul_vec3(Vec3::new(size.x / 2., -size.y / 2., 0.)).truncate();
+            let shape = match object.ellipse {
+                true => ShapeDef::Ball { radius: size.x.max(size.y) / 2. },
+                false => ShapeDef::Cuboid { half_extents: size / 2. },
+            };
+            (center, None, Some(shape))
+        };
+
+        let container = match string_property(properties, "container")? {
+            Some(name) => Some(ContainerDef {
+                name,
+                slots: int_property(properties, "slots")?.unwrap_or(1) as usize,
+                loot: string_property(properties, "loot")?,
+            }),
+            None => None,
+        };
+        let station = match string_property(properties, "station")? {
+            Some(kind) => Some(StationDef {
+                kind,
+                radius: float_property(properties, "station_radius")?.unwrap_or(DEFAULT_STATION_RADIUS),
+            }),
+            None => None,
+        };
+
+        self.level.props.push(PropDef {
+            prefab: string_property(properties, "prefab")?,
+            name: (!object.name.is_empty()).then(|| object.name.clone()),
+            position,
+            z: float_property(properties, "z")?.unwrap_or(1.),
+            rotation,
+            visual,
+            flip_x: object.gid.map(|gid| gid & FLIPPED_HORIZONTALLY != 0),
+            flip_y: object.gid.map(|gid| gid & FLIPPED_VERTICALLY != 0),
+            layer: match string_property(properties, "sorting_layer")?.as_deref() {
+                Some("Ground") => Some(SortingLayer::Ground),
+                Some("Props") => Some(SortingLayer::Props),
+                Some("Characters") => Some(SortingLayer::Characters),
+                Some("Overhead") => Some(SortingLayer::Overhead),
+                None => None,
+                Some(_) => return Err(TiledError::InvalidProperty("sorting_layer".into())),
+            },
+            pivot: float_property(properties, "pivot")?,
+            colliders: shape.map(|shape| vec![ColliderDef {
+                shape,
+                offset: Vec2::ZERO,
+                rotation: 0.,
+                layer,
+            }]),
+            occluder: bool_property(properties, "occluder")?,
+            container,
+            station,
+            interactable: string_property(properties, "interactable")?,
+        });
+        Ok(())
+    }
+}
+
+/// Parses a `.tmj` map into a level, with asset paths relative to the
+/// assets folder. `path` is where the map itself lives.
+pub fn level_from_tmj(bytes: &[u8], path: &Path) -> Result<Level, bevy::asset::Error> {
+    let map: Map = serde_json::from_slice(bytes)?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+
+    let mut converter = Converter::new(&map, directory)?;
+    for layer in map.layers.iter() {
+        converter.layer(layer, Vec2::ZERO)?;
+    }
+    Ok(converter.level)
+}
+
+#[derive(Default)]
+pub struct TiledMapLoader;
+
+impl AssetLoader for TiledMapLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let level = level_from_tmj(bytes, load_context.path())?;
+            load_context.set_default_asset(LoadedAsset::new(level));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tmj"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> Level {
+        level_from_tmj(include_bytes!("../assets/maps/fixture.tmj"), Path::new("maps/fixture.tmj")).unwrap()
+    }
+
+    fn prop<'a>(level: &'a Level, name: &str) -> &'a PropDef {
+        level.props.iter().find(|prop| prop.name.as_deref() == Some(name)).unwrap()
+    }
+
+    fn shape(prop: &PropDef) -> &ShapeDef {
+        &prop.colliders.as_ref().unwrap()[0].shape
+    }
+
+    fn error(json: &str) -> TiledError {
+        let error = level_from_tmj(json.as_bytes(), Path::new("maps/bad.tmj")).unwrap_err();
+        error.downcast::<TiledError>().unwrap()
+    }
+
+    #[test]
+    fn fixture_spawn_points_and_items() {
+        let level = fixture();
+        assert_eq!(level.spawn_point("player"), Some(Vec2::ZERO));
+        assert_eq!(level.bounds, Some(Rect::new(-256., -192., 256., 192.)));
+
+        let [berries] = level.items.as_slice() else {
+            panic!("expected one item, got {:?}", level.items);
+        };
+        assert_eq!(berries.item, ItemId::from("berries"));
+        assert_eq!(berries.quantity, 3);
+        assert_eq!(berries.position, Vec2::new(-108., 64.));
+    }
+
+    #[test]
+    fn fixture_colliders() {
+        let level = fixture();
+        assert_eq!(level.props.len(), 6);
+
+        let wall = prop(&level, "Tent wall");
+        let rotation = -20_f32.to_radians();
+        let center = Vec2::new(128., 136.) + Vec2::from_angle(rotation).rotate(Vec2::new(1., -83.));
+        assert!(wall.position.abs_diff_eq(center, 1e-3), "{}", wall.position);
+        assert!((wall.rotation - rotation).abs() < 1e-6);
+        assert!(matches!(shape(wall), ShapeDef::Cuboid { half_extents } if *half_extents == Vec2::new(1., 83.)));
+
+        let rock = prop(&level, "Rock");
+        assert_eq!(rock.position, Vec2::new(164., -108.));
+        assert_eq!(rock.occluder, Some(false));
+        assert!(matches!(shape(rock), ShapeDef::Ball { radius } if *radius == 20.));
+
+        let campfire = prop(&level, "Campfire");
+        assert_eq!(campfire.position, Vec2::new(-42., -22.));
+        assert!(matches!(shape(campfire), ShapeDef::Ball { radius } if *radius == 14.));
+        let station = campfire.station.as_ref().unwrap();
+        assert_eq!((station.kind.as_str(), station.radius), ("campfire", 80.));
+
+        let puddle = prop(&level, "Puddle");
+        assert_eq!(puddle.position, Vec2::new(-196., 132.));
+        assert_eq!(puddle.colliders.as_ref().unwrap()[0].layer, ColliderLayer::Walkable);
+        let ShapeDef::Polygon { points } = shape(puddle) else {
+            panic!("puddle should be a polygon");
+        };
+        assert_eq!(points, &[
+            Vec2::new(0., 0.),
+            Vec2::new(50., -10.),
+            Vec2::new(30., -30.),
+            Vec2::new(60., -50.),
+            Vec2::new(0., -40.),
+        ]);
+    }
+
+    #[test]
+    fn fixture_tile_objects() {
+        let level = fixture();
+
+        let tent = prop(&level, "Tent");
+        assert_eq!(tent.position, Vec2::new(136., 64.));
+        assert!(tent.colliders.is_none());
+        assert!(matches!(&tent.visual, Some(Visual::Texture(path)) if path == "textures/stationary/tent.png"));
+        let container = tent.container.as_ref().unwrap();
+        assert_eq!((container.name.as_str(), container.slots, container.loot.as_deref()), ("Tent", 10, Some("tent")));
+
+        let log = prop(&level, "Sitting log");
+        assert_eq!(log.prefab.as_deref(), Some("sitting_log"));
+        assert_eq!(log.interactable.as_deref(), Some("Sitting log"));
+    }
+
+    #[test]
+    fn fixture_tile_layer() {
+        let level = fixture();
+
+        // the image collection doesn't become an atlas
+        let [tileset] = level.tilesets.as_slice() else {
+            panic!("expected one tileset");
+        };
+        assert_eq!(tileset.image, "background/campsite-improved.png");
+        assert_eq!((tileset.columns, tileset.rows), (53, 32));
+
+        let [layer] = level.tile_layers.as_slice() else {
+            panic!("expected one tile layer");
+        };
+        assert_eq!(layer.tiles.len(), 47);
+        assert!(layer.tiles.iter().all(|tile| !tile.flip_x && !tile.flip_y));
+
+        let first = &layer.tiles[0];
+        assert_eq!((first.tileset, first.index), (0, 550));
+        assert_eq!(first.position, Vec2::new(-224., 160.));
+    }
+
+    #[test]
+    fn flip_flags() {
+        let level = level_from_tmj(format!(r#"{{
+            "width": 2, "height": 1, "tilewidth": 16, "tileheight": 16,
+            "tilesets": [{{"firstgid": 1, "image": "tiles.png", "tilewidth": 16, "tileheight": 16, "columns": 2, "tilecount": 4}}],
+            "layers": [{{"type": "tilelayer", "width": 2, "data": [{}, {}]}}]
+        }}"#, 2 | FLIPPED_HORIZONTALLY, 4 | FLIPPED_VERTICALLY).as_bytes(), Path::new("maps/flips.tmj")).unwrap();
+
+        let tiles = &level.tile_layers[0].tiles;
+        assert_eq!((tiles[0].index, tiles[0].flip_x, tiles[0].flip_y), (1, true, false));
+        assert_eq!((tiles[1].index, tiles[1].flip_x, tiles[1].flip_y), (3, false, true));
+    }
+
+    #[test]
+    fn external_tileset() {
+        let error = error(r#"{
+            "width": 1, "height": 1, "tilewidth": 16, "tileheight": 16,
+            "tilesets": [{"firstgid": 1, "source": "ground.tsx"}],
+            "layers": []
+        }"#);
+        assert!(matches!(error, TiledError::ExternalTileset(source) if source == "ground.tsx"));
+    }
+
+    #[test]
+    fn invalid_property() {
+        let error = error(r#"{
+            "width": 1, "height": 1, "tilewidth": 16, "tileheight": 16,
+            "layers": [{"type": "objectgroup", "objects": [
+                {"x": 0, "y": 0, "width": 8, "height": 8, "properties": [{"name": "slots", "type": "string", "value": "many"}, {"name": "container", "type": "string", "value": "Box"}]}
+            ]}]
+        }"#);
+        assert!(matches!(error, TiledError::InvalidProperty(name) if name == "slots"));
+    }
+
+    #[test]
+    fn tile_out_of_range() {
+        let error = error(r#"{
+            "width": 1, "height": 1, "tilewidth": 16, "tileheight": 16,
+            "tilesets": [{"firstgid": 1, "image": "tiles.png", "tilewidth": 16, "tileheight": 16, "columns": 2, "tilecount": 4}],
+            "layers": [{"type": "tilelayer", "width": 1, "data": [5]}]
+        }"#);
+        assert!(matches!(error, TiledError::TileOutOfRange(5)));
+    }
+
+    #[test]
+    fn zero_width() {
+        let error = error(r#"{
+            "width": 1, "height": 1, "tilewidth": 16, "tileheight": 16,
+            "tilesets": [{"firstgid": 1, "image": "tiles.png", "tilewidth": 16, "tileheight": 16, "columns": 2, "tilecount": 4}],
+            "layers": [{"type": "tilelayer", "name": "Ground", "width": 0, "data": [1]}]
+        }"#);
+        assert!(matches!(error, TiledError::ZeroWidth(name) if name == "Ground"));
+    }
+
+    #[test]
+    fn group_offsets_add_up() {
+        let level = level_from_tmj(br#"{
+            "width": 2, "height": 2, "tilewidth": 16, "tileheight": 16,
+            "layers": [{"type": "group", "offsetx": 10, "offsety": 20, "layers": [
+                {"type": "objectgroup", "offsetx": 1, "offsety": 2, "objects": [{"name": "player", "x": 0, "y": 0, "point": true}]},
+                {"type": "imagelayer", "image": "sky.png"}
+            ]}]
+        }"#, Path::new("maps/groups.tmj")).unwrap();
+
+        assert_eq!(level.spawn_point("player"), Some(Vec2::new(-5., -6.)));
+        assert_eq!(level.backgrounds[0].position, Vec2::new(-6., -4.));
+    }
+
+    #[test]
+    fn hidden_layers() {
+        let level = level_from_tmj(br#"{
+            "width": 2, "height": 2, "tilewidth": 16, "tileheight": 16,
+            "layers": [
+                {"type": "objectgroup", "objects": [{"name": "shown", "x": 0, "y": 0, "point": true}]},
+                {"type": "objectgroup", "visible": false, "objects": [{"name": "hidden", "x": 0, "y": 0, "point": true}]},
+                {"type": "group", "visible": false, "layers": [
+                    {"type": "objectgroup", "objects": [{"name": "in hidden group", "x": 0, "y": 0, "point": true}]}
+                ]}
+            ]
+        }"#, Path::new("maps/hidden.tmj")).unwrap();
+
+        assert!(level.spawn_point("shown").is_some());
+        assert_eq!(level.spawn_point("hidden"), None);
+        assert_eq!(level.spawn_point("in hidden group"), None);
+    }
+
+    #[test]
+    fn tile_object_flips() {
+        let level = level_from_tmj(format!(r#"{{
+            "width": 2, "height": 2, "tilewidth": 16, "tileheight": 16,
+            "tilesets": [{{"firstgid": 1, "image": "tiles.png", "tilewidth": 16, "tileheight": 16, "columns": 2, "tilecount": 4}}],
+            "layers": [{{"type": "objectgroup", "objects": [
+                {{"name": "Crate", "x": 0, "y": 16, "width": 16, "height": 16, "gid": {}}},
+                {{"name": "Wall", "x": 0, "y": 0, "width": 16, "height": 16}}
+            ]}}]
+        }}"#, 3 | FLIPPED_HORIZONTALLY).as_bytes(), Path::new("maps/flips.tmj")).unwrap();
+
+        let crate_prop = prop(&level, "Crate");
+        assert!(matches!(crate_prop.visual, Some(Visual::Tile { index: 2, .. })));
+        assert_eq!((crate_prop.flip_x, crate_prop.flip_y), (Some(true), Some(false)));
+        let wall = prop(&level, "Wall");
+        assert_eq!((wall.flip_x, wall.flip_y), (None, None));
+    }
+}